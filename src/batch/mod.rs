@@ -0,0 +1,327 @@
+use chrono::Local;
+use serde::Serialize;
+use serde_json::Value;
+use std::{collections::BTreeMap, error::Error, fs::{self, File}, path::{Path, PathBuf}, process::{Command, Stdio}, sync::{Mutex, atomic::{AtomicUsize, Ordering}}, thread};
+
+use crate::{cli::{BatchArgs, ConfigArgs}, config, output::{data_file::numbered_path, metadata::metadata_path}};
+
+// Values compared across runs, as named in the summary and where they are in the run metadata
+const SUMMARY_VALUES: [(&str, &str); 9] = [
+    ("sim_time", "/sim_time"),
+    ("steps", "/steps"),
+    ("wall_clock_seconds", "/wall_clock_seconds"),
+    ("at_rest", "/fragments/at_rest"),
+    ("out_of_bounds", "/fragments/out_of_bounds"),
+    ("escaped", "/fragments/escaped"),
+    ("mean_distance", "/fragments/mean_distance"),
+    ("max_distance", "/fragments/max_distance"),
+    ("mean_speed", "/fragments/mean_speed"),
+];
+
+// One replication of the batch
+#[derive(Clone)]
+struct Run {
+    number: u32,
+    seed: u64,
+    data_file: PathBuf,
+}
+
+// How a run went, read back from its exit code and metadata file
+struct Outcome {
+    run: Run,
+    // None if the process could not be started or was killed
+    exit_code: Option<i32>,
+    metadata: Option<Value>,
+}
+
+impl Outcome {
+    fn succeeded(&self) -> bool {
+        self.exit_code == Some(0) && self.metadata.is_some()
+    }
+
+    // A number from the metadata, missing for failed runs
+    fn value(&self, pointer: &str) -> Option<f64> {
+        self.metadata.as_ref()?.pointer(pointer)?.as_f64()
+    }
+}
+
+// Aggregate of all runs, written to summary.json
+#[derive(Serialize)]
+struct BatchSummary {
+    runs: u32,
+    succeeded: u32,
+    first_seed: u64,
+    failed_seeds: Vec<u64>,
+    end_reasons: BTreeMap<String, u32>,
+    statistics: BTreeMap<&'static str, Statistic>,
+}
+
+// Spread of one value over the successful runs
+#[derive(Serialize)]
+struct Statistic {
+    mean: f64,
+    std: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Statistic {
+    fn from_values(values: &[f64]) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+
+        let count = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / count;
+        // Sample standard deviation, 0 for a single run
+        let variance = if values.len() > 1 {
+            values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / (count - 1.0)
+        } else {
+            0.0
+        };
+
+        Some(Statistic {
+            mean,
+            std: variance.sqrt(),
+            min: values.iter().copied().fold(f64::INFINITY, f64::min),
+            max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        })
+    }
+}
+
+// Run every replication as a headless child process and summarize them, returns the exit code
+pub fn run_batch(args: BatchArgs) -> i32 {
+    // Check the config once before starting any runs
+    let config = config::load(&args.config);
+    config.print_report();
+    if config.has_errors() {
+        return 1;
+    }
+    let sim_settings = config.sim_settings;
+
+    // Every run gets the next seed after the first one
+    let first_seed = sim_settings.seed.unwrap_or_else(rand::random);
+
+    // Put the batch next to csv_location unless a directory is given
+    let data_location = PathBuf::from(&sim_settings.csv_location);
+    let out_dir = args.out_dir.clone().unwrap_or_else(|| {
+        let parent = data_location.parent().unwrap_or_else(|| Path::new(""));
+        parent.join(format!("batch_{}", Local::now().format("%Y%m%d_%H%M%S")))
+    });
+    if let Err(err) = fs::create_dir_all(&out_dir) {
+        println!("Could not create batch directory {}: {}", out_dir.display(), err);
+        return 1;
+    }
+
+    // Name the data files of the runs after csv_location
+    let data_name = data_location.file_name().map(PathBuf::from).unwrap_or_else(|| PathBuf::from("data.csv"));
+    let runs = plan_runs(&out_dir.join(data_name), first_seed, args.runs);
+
+    println!("Running {} runs with seeds {} to {} in {}.", args.runs, first_seed, first_seed.wrapping_add(u64::from(args.runs - 1)), out_dir.display());
+
+    // Each worker thread takes the next run until none are left
+    let next = AtomicUsize::new(0);
+    let outcomes = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..args.jobs {
+            scope.spawn(|| {
+                while let Some(run) = runs.get(next.fetch_add(1, Ordering::SeqCst)) {
+                    let outcome = run_worker(&args.config, run);
+                    let status = match outcome.exit_code {
+                        Some(0) => "finished".to_string(),
+                        Some(code) => format!("failed with exit code {}", code),
+                        None => "failed to run".to_string(),
+                    };
+                    println!("Run {}/{} with seed {} {}.", run.number, args.runs, run.seed, status);
+                    outcomes.lock().unwrap().push(outcome);
+                }
+            });
+        }
+    });
+
+    let mut outcomes = outcomes.into_inner().unwrap();
+    outcomes.sort_by_key(|outcome| outcome.run.number);
+
+    if let Err(err) = write_summary(&out_dir, first_seed, &outcomes) {
+        println!("Could not write the batch summary to {}: {}", out_dir.display(), err);
+        return 1;
+    }
+
+    let succeeded = outcomes.iter().filter(|outcome| outcome.succeeded()).count();
+    println!("{} of {} runs succeeded. Summary written to {}.", succeeded, outcomes.len(), out_dir.join("summary.json").display());
+
+    if succeeded == outcomes.len() { 0 } else { 1 }
+}
+
+// Number the runs from 1 and give each the next seed after the first one
+// Their data files are numbered too, data.csv becomes data_001.csv, data_002.csv, ...
+fn plan_runs(data_file: &Path, first_seed: u64, runs: u32) -> Vec<Run> {
+    (1..=runs)
+        .map(|number| Run {
+            number,
+            seed: first_seed.wrapping_add(u64::from(number - 1)),
+            data_file: numbered_path(data_file, number),
+        })
+        .collect()
+}
+
+// Simulate one run in a child process, logging its output next to its data file
+fn run_worker(config_args: &ConfigArgs, run: &Run) -> Outcome {
+    // Do not mistake metadata left from an earlier batch in the same directory for this run
+    let _ = fs::remove_file(metadata_path(&run.data_file));
+
+    let log_path = run.data_file.with_extension("log");
+    let exit_code = File::create(&log_path)
+        .and_then(|log| {
+            let mut command = Command::new(std::env::current_exe()?);
+            command.args(["run", "--headless"]);
+            if let Some(config_path) = &config_args.config {
+                command.arg("--config").arg(config_path);
+            }
+            for setting in &config_args.overrides {
+                command.arg("--set").arg(setting);
+            }
+
+            // Every run writes its own fresh file in the batch directory
+            command.args(["--set", "output_mode=overwrite", "--set", "run_directories=false"]);
+            command.arg("--out").arg(&run.data_file);
+            command.arg("--seed").arg(run.seed.to_string());
+
+            command.stdout(Stdio::from(log.try_clone()?)).stderr(Stdio::from(log)).status()
+        })
+        .map(|status| status.code())
+        .unwrap_or_else(|err| {
+            println!("Could not start run {}: {}", run.number, err);
+            None
+        });
+
+    read_outcome(run, exit_code)
+}
+
+// The metadata holds the end of the run and where the fragments ended up
+fn read_outcome(run: &Run, exit_code: Option<i32>) -> Outcome {
+    let metadata = fs::read_to_string(metadata_path(&run.data_file))
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok());
+
+    Outcome { run: run.clone(), exit_code, metadata }
+}
+
+// Write summary.csv with a row per run and summary.json with statistics over the successful runs
+fn write_summary(out_dir: &Path, first_seed: u64, outcomes: &[Outcome]) -> Result<(), Box<dyn Error>> {
+    let mut wtr = csv::Writer::from_path(out_dir.join("summary.csv"))?;
+
+    let mut header = vec!["run", "seed", "exit_code", "end_reason"];
+    header.extend(SUMMARY_VALUES.iter().map(|(name, _)| *name));
+    header.push("data_file");
+    wtr.write_record(&header)?;
+
+    for outcome in outcomes {
+        let mut record = vec![
+            outcome.run.number.to_string(),
+            outcome.run.seed.to_string(),
+            outcome.exit_code.map(|code| code.to_string()).unwrap_or_default(),
+            end_reason(outcome).unwrap_or_default(),
+        ];
+        record.extend(SUMMARY_VALUES.iter().map(|(_, pointer)| outcome.value(pointer).map(|value| value.to_string()).unwrap_or_default()));
+        record.push(outcome.run.data_file.display().to_string());
+        wtr.write_record(&record)?;
+    }
+    wtr.flush()?;
+
+    let succeeded: Vec<&Outcome> = outcomes.iter().filter(|outcome| outcome.succeeded()).collect();
+
+    let mut end_reasons = BTreeMap::new();
+    for reason in succeeded.iter().filter_map(|outcome| end_reason(outcome)) {
+        *end_reasons.entry(reason).or_insert(0) += 1;
+    }
+
+    let statistics = SUMMARY_VALUES
+        .iter()
+        .filter_map(|(name, pointer)| {
+            let values: Vec<f64> = succeeded.iter().filter_map(|outcome| outcome.value(pointer)).collect();
+            Statistic::from_values(&values).map(|statistic| (*name, statistic))
+        })
+        .collect();
+
+    let summary = BatchSummary {
+        runs: outcomes.len() as u32,
+        succeeded: succeeded.len() as u32,
+        first_seed,
+        failed_seeds: outcomes.iter().filter(|outcome| !outcome.succeeded()).map(|outcome| outcome.run.seed).collect(),
+        end_reasons,
+        statistics,
+    };
+
+    let file = File::create(out_dir.join("summary.json"))?;
+    serde_json::to_writer_pretty(file, &summary)?;
+    Ok(())
+}
+
+// Why a run ended, as written in its metadata
+fn end_reason(outcome: &Outcome) -> Option<String> {
+    outcome.metadata.as_ref()?.get("end_reason")?.as_str().map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    #[test]
+    fn runs_count_up_from_the_first_seed() {
+        let runs = plan_runs(Path::new("out/data.csv"), 41, 3);
+        let runs: Vec<_> = runs.iter().map(|run| (run.number, run.seed, run.data_file.clone())).collect();
+        assert_eq!(runs, [
+            (1, 41, PathBuf::from("out/data_001.csv")),
+            (2, 42, PathBuf::from("out/data_002.csv")),
+            (3, 43, PathBuf::from("out/data_003.csv")),
+        ]);
+
+        // Seeds wrap around instead of overflowing
+        let seeds: Vec<_> = plan_runs(Path::new("data.csv"), u64::MAX, 2).iter().map(|run| run.seed).collect();
+        assert_eq!(seeds, [u64::MAX, 0]);
+    }
+
+    #[test]
+    fn summary_of_two_runs() {
+        let dir = env::temp_dir().join(format!("frag-sim-batch-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        // Two finished runs with the metadata a headless run writes, and one that failed before writing any
+        let runs = plan_runs(&dir.join("data.csv"), 7, 3);
+        let metadata = [
+            r#"{"end_reason": "at_rest", "sim_time": 2.0, "steps": 120, "wall_clock_seconds": 1.5, "fragments": {"count": 10, "at_rest": 10, "escaped": 0, "out_of_bounds": 0, "mean_distance": 4.0, "max_distance": 9.0, "mean_speed": 0.0}}"#,
+            r#"{"end_reason": "max_time", "sim_time": 4.0, "steps": 240, "wall_clock_seconds": 2.5, "fragments": {"count": 8, "at_rest": 6, "escaped": 2, "out_of_bounds": 1, "mean_distance": 6.0, "max_distance": 12.0, "mean_speed": 1.0}}"#,
+        ];
+        for (run, metadata) in runs.iter().zip(metadata) {
+            fs::write(metadata_path(&run.data_file), metadata).unwrap();
+        }
+        let outcomes: Vec<_> = runs.iter().zip([Some(0), Some(0), Some(1)]).map(|(run, exit_code)| read_outcome(run, exit_code)).collect();
+        write_summary(&dir, 7, &outcomes).unwrap();
+
+        // A row per run with its seed and values, left empty for the failed run
+        let mut rdr = csv::Reader::from_path(dir.join("summary.csv")).unwrap();
+        let header = rdr.headers().unwrap().clone();
+        let rows: Vec<csv::StringRecord> = rdr.records().map(Result::unwrap).collect();
+        let column = |row: &csv::StringRecord, name: &str| row[header.iter().position(|header| header == name).unwrap()].to_string();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows.iter().map(|row| column(row, "seed")).collect::<Vec<_>>(), ["7", "8", "9"]);
+        assert_eq!(rows.iter().map(|row| column(row, "end_reason")).collect::<Vec<_>>(), ["at_rest", "max_time", ""]);
+        assert_eq!(rows.iter().map(|row| column(row, "mean_distance")).collect::<Vec<_>>(), ["4", "6", ""]);
+        assert_eq!(column(&rows[1], "data_file"), dir.join("data_002.csv").display().to_string());
+
+        // Statistics over the two runs that succeeded
+        let summary: Value = serde_json::from_str(&fs::read_to_string(dir.join("summary.json")).unwrap()).unwrap();
+        assert_eq!(summary["runs"], 3);
+        assert_eq!(summary["succeeded"], 2);
+        assert_eq!(summary["first_seed"], 7);
+        assert_eq!(summary["failed_seeds"], serde_json::json!([9]));
+        assert_eq!(summary["end_reasons"], serde_json::json!({"at_rest": 1, "max_time": 1}));
+        assert_eq!(summary["statistics"]["sim_time"], serde_json::json!({"mean": 3.0, "std": 2.0_f64.sqrt(), "min": 2.0, "max": 4.0}));
+        assert_eq!(summary["statistics"]["escaped"]["mean"], 1.0);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,98 @@
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+// Command line of frag-sim, parsed once at startup
+#[derive(Parser)]
+#[command(version, about = "Simulate fragmentation grenade explosions")]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    // Without a subcommand the menu is opened, or a headless run is started with --headless
+    #[command(flatten)]
+    pub run: RunArgs,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Open the menu, the config is read each time a simulation is started
+    Gui(ConfigArgs),
+    /// Start a simulation right away
+    Run(RunArgs),
+    /// Run many headless replications of one config with different seeds and summarize them
+    Batch(BatchArgs),
+    /// Check a config file and list its errors and warnings
+    Validate(ValidateArgs),
+    /// Play back the fragments of a long layout csv data file
+    Replay(ReplayArgs),
+}
+
+// Where the settings of a simulation come from
+#[derive(Args, Clone, Default)]
+pub struct ConfigArgs {
+    /// Config file, defaults to the first of config.toml, config.ron, config.json and config.txt
+    #[arg(short = 'c', long)]
+    pub config: Option<PathBuf>,
+
+    /// Data file to write, replaces csv_location
+    #[arg(short = 'o', long)]
+    pub out: Option<String>,
+
+    /// Seed for all random numbers, replaces the seed from the config file
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Replace a setting of the config file, can be given more than once
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    pub overrides: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct RunArgs {
+    #[command(flatten)]
+    pub config: ConfigArgs,
+
+    /// Run without a window, GPU, audio or menu and exit when the simulation ends
+    #[arg(long)]
+    pub headless: bool,
+}
+
+#[derive(Args)]
+pub struct BatchArgs {
+    // Settings shared by every run, --out names the data files and --seed is the seed of the first run
+    #[command(flatten)]
+    pub config: ConfigArgs,
+
+    /// Number of runs
+    #[arg(short = 'n', long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub runs: u32,
+
+    /// Number of runs simulated at the same time, each in its own process
+    #[arg(short = 'j', long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub jobs: u32,
+
+    /// Directory for the data of every run and the summary, defaults to a timestamped directory next to csv_location
+    #[arg(long)]
+    pub out_dir: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct ValidateArgs {
+    /// Config file to check
+    pub config: PathBuf,
+
+    /// Replace a setting of the config file before checking it
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    pub overrides: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct ReplayArgs {
+    /// Data file written with the long layout
+    pub data: PathBuf,
+
+    /// Playback speed, 2 plays twice as fast as simulated
+    #[arg(long, default_value_t = 1.0)]
+    pub speed: f32,
+}
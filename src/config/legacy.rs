@@ -0,0 +1,85 @@
+use serde_json::Value;
+
+use crate::physics::sim_settings::SimSettings;
+
+use super::{setting_pointer, ConfigEntry};
+
+// Read the original config.txt format, one key:value pair per line
+pub fn read_entries(text: &str) -> Vec<ConfigEntry> {
+    // The defaults tell which settings are text
+    let defaults = serde_json::to_value(SimSettings::default()).unwrap_or_default();
+
+    text.lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            // Skip lines that are not a key and value
+            let (key, raw) = line.split_once(':')?;
+            Some(text_entry(&defaults, key, raw, Some(index + 1)))
+        })
+        .collect()
+}
+
+// A setting written as text, as in config.txt or a --set flag
+pub fn text_entry(defaults: &Value, key: &str, raw: &str, line: Option<usize>) -> ConfigEntry {
+    let key = key.trim().to_lowercase();
+    let value = legacy_value(defaults.pointer(&setting_pointer(&key)), raw.trim());
+    ConfigEntry { key, value, line }
+}
+
+// Turn the text after the colon into a json value
+fn legacy_value(default: Option<&Value>, raw: &str) -> Value {
+    // Text settings are kept as written, even when they look like a number
+    if default.is_some_and(Value::is_string) {
+        return Value::String(raw.to_string());
+    }
+
+    // Vectors are written as <x, y, z>, anything that is not json is passed on as text
+    let json = raw.replace('<', "[").replace('>', "]");
+    serde_json::from_str(&json).unwrap_or_else(|_| Value::String(raw.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::apply_entries, output::csv_output::OutputLayout};
+    use bevy::math::Vec3;
+
+    #[test]
+    fn baseline_config_txt() {
+        // The config.txt shipped with the original version
+        let text = "\
+fuse_time:5.0
+lin_vel:<7.0, 2.0, 7.0>
+ang_vel:<1.0, 2.0, 1.0>
+friction:10.0
+restitution:0.9
+initial_height:5.0
+fragment_count:1000
+explosion_vel:480.0
+csv_location:data1.csv";
+        let (sim_settings, diagnostics) = apply_entries(read_entries(text));
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics.iter().map(|diagnostic| &diagnostic.message).collect::<Vec<_>>());
+
+        assert_eq!(sim_settings.fuse_time, 5.0);
+        assert_eq!(sim_settings.lin_vel, Vec3::new(7.0, 2.0, 7.0));
+        assert_eq!(sim_settings.ang_vel, Vec3::new(1.0, 2.0, 1.0));
+        assert_eq!(sim_settings.friction, 10.0);
+        assert_eq!(sim_settings.restitution, 0.9);
+        assert_eq!(sim_settings.initial_height, 5.0);
+        assert_eq!(sim_settings.fragment_count, 1000);
+        assert_eq!(sim_settings.explosion_vel, Some(480.0));
+        assert_eq!(sim_settings.csv_location, "data1.csv");
+    }
+
+    #[test]
+    fn text_settings_are_kept_as_written() {
+        // Numbers, colons and capitals in text settings are not parsed
+        let text = "csv_location: C:/data/2024.csv\nmass_histogram:1000\nOutput_Layout:Legacy\n";
+        let (sim_settings, diagnostics) = apply_entries(read_entries(text));
+        assert!(diagnostics.is_empty());
+
+        assert_eq!(sim_settings.csv_location, "C:/data/2024.csv");
+        assert_eq!(sim_settings.mass_histogram, "1000");
+        assert_eq!(sim_settings.output_layout, OutputLayout::Legacy);
+    }
+}
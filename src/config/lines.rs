@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+
+use super::ConfigFormat;
+
+// Line each setting is first given a value on, by its full name such as arena.walls.north
+// Only keys at the start of a line are found, at the top level or in a [section] or group of settings
+pub fn setting_lines(text: &str, format: ConfigFormat) -> HashMap<String, usize> {
+    let mut lines = HashMap::new();
+
+    // Groups the current line is in, None inside a list where keys are not settings
+    let mut groups: Vec<Option<String>> = vec![];
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.starts_with('#') || line.starts_with("//") {
+            continue;
+        }
+
+        match format {
+            ConfigFormat::Toml if line.starts_with('[') => {
+                // Every key after a [section] or [[list]] header is in that section
+                let section = line.trim_matches(['[', ']']).trim().to_string();
+                lines.entry(section.clone()).or_insert(index + 1);
+                groups = vec![Some(section)];
+            }
+            ConfigFormat::Toml => {
+                if let Some(key) = line.split_once('=').and_then(|(key, _)| setting_name(key)) {
+                    let section = groups.last().cloned().flatten();
+                    lines.entry(full_name(section.as_deref(), &key)).or_insert(index + 1);
+                }
+            }
+            ConfigFormat::Ron | ConfigFormat::Json => {
+                // A line closing a group leaves it
+                if line.starts_with(['}', ')', ']']) {
+                    groups.pop();
+                    continue;
+                }
+
+                let group = groups.last().cloned();
+                let mut name = None;
+                let mut value = line;
+                if let Some((key, rest)) = line.split_once(':') {
+                    if let Some(key) = setting_name(key) {
+                        value = rest.trim();
+
+                        // Keys outside the outermost brackets or inside a list are not settings
+                        if let Some(Some(group)) = &group {
+                            let full = full_name(Some(group.as_str()).filter(|group| !group.is_empty()), &key);
+                            lines.entry(full.clone()).or_insert(index + 1);
+                            name = Some(full);
+                        }
+                    }
+                }
+
+                // A line ending in an open bracket starts a group, or the settings themselves when it is the first
+                if value.ends_with(['{', '(']) {
+                    groups.push(if group.is_none() { Some(String::new()) } else { name });
+                } else if value.ends_with('[') {
+                    groups.push(None);
+                }
+            }
+            // Legacy entries are read a line at a time and know their own line
+            ConfigFormat::Legacy => {}
+        }
+    }
+
+    lines
+}
+
+// Line of a setting, or of the group it was written in when it is part of an inline table
+pub fn line_of(lines: &HashMap<String, usize>, key: &str) -> Option<usize> {
+    let mut key = key;
+    loop {
+        if let Some(line) = lines.get(key) {
+            return Some(*line);
+        }
+        key = &key[..key.rfind('.')?];
+    }
+}
+
+// Name of a setting without quotes, None if it is not a name
+fn setting_name(key: &str) -> Option<String> {
+    let name = key.trim().trim_matches(['"', '\'']);
+    let is_name = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.');
+    is_name.then(|| name.to_string())
+}
+
+fn full_name(group: Option<&str>, key: &str) -> String {
+    match group {
+        Some(group) => format!("{}.{}", group, key),
+        None => key.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml_keys_are_found_in_their_own_section() {
+        let text = "\
+# width = 1 in a comment
+csv_location = \"width = 2\"
+arena.height = 3
+[arena.walls]
+north = false
+[arena]
+depth = 4 # width = 5
+width = 6
+";
+        let lines = setting_lines(text, ConfigFormat::Toml);
+        assert_eq!(lines.get("csv_location"), Some(&2));
+        assert_eq!(lines.get("arena.height"), Some(&3));
+        assert_eq!(lines.get("arena.walls.north"), Some(&5));
+        assert_eq!(lines.get("arena.depth"), Some(&7));
+        assert_eq!(lines.get("arena.width"), Some(&8));
+        assert_eq!(lines.get("width"), None);
+    }
+
+    #[test]
+    fn inline_tables_give_the_line_of_their_group() {
+        let text = "arena = { depth = 2 }\n";
+        let lines = setting_lines(text, ConfigFormat::Toml);
+        assert_eq!(line_of(&lines, "arena.depth"), Some(1));
+        assert_eq!(line_of(&lines, "seed"), None);
+    }
+
+    #[test]
+    fn ron_keys_are_found_in_their_own_group() {
+        let text = "\
+(
+    // width: 1
+    csv_location: \"width: 2\",
+    arena: (
+        obstacles: [
+            (
+                path: \"a.glb\",
+            ),
+        ],
+        walls: (north: false),
+        width: 5,
+    ),
+)
+";
+        let lines = setting_lines(text, ConfigFormat::Ron);
+        assert_eq!(lines.get("csv_location"), Some(&3));
+        assert_eq!(lines.get("arena.obstacles"), Some(&5));
+        assert_eq!(line_of(&lines, "arena.walls.north"), Some(10));
+        assert_eq!(lines.get("arena.width"), Some(&11));
+        assert_eq!(lines.get("width"), None);
+        assert_eq!(lines.get("arena.path"), None);
+    }
+
+    #[test]
+    fn json_keys_are_found_in_their_own_object() {
+        let text = "{\n  \"csv_location\": \"width: 1\",\n  \"lin_vel\": [1, 2, 3],\n  \"arena\": {\n    \"walls\": { \"north\": false },\n    \"width\": 2\n  }\n}\n";
+        let lines = setting_lines(text, ConfigFormat::Json);
+        assert_eq!(lines.get("csv_location"), Some(&2));
+        assert_eq!(lines.get("lin_vel"), Some(&3));
+        assert_eq!(line_of(&lines, "arena.walls.north"), Some(5));
+        assert_eq!(lines.get("arena.width"), Some(&6));
+        assert_eq!(lines.get("width"), None);
+    }
+}
@@ -0,0 +1,271 @@
+use serde::{de::Error as _, Deserialize, Deserializer};
+use serde_json::{Map, Value};
+use std::{collections::HashMap, fmt::Display, fs, path::{Path, PathBuf}, str::FromStr};
+
+use crate::{cli::ConfigArgs, physics::sim_settings::SimSettings};
+
+use self::validation::{Diagnostic, Severity};
+
+pub mod legacy;
+pub mod lines;
+pub mod validation;
+
+// Config files looked for in the working directory when no -c flag is given
+const DEFAULT_CONFIG_PATHS: [&str; 4] = ["config.toml", "config.ron", "config.json", "config.txt"];
+
+// Syntax of a config file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Ron,
+    Json,
+    // The original key:value lines of config.txt
+    Legacy,
+}
+
+impl ConfigFormat {
+    // Pick the format from the file extension, anything unknown is read as the legacy format
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => ConfigFormat::Toml,
+            Some(ext) if ext.eq_ignore_ascii_case("ron") => ConfigFormat::Ron,
+            Some(ext) if ext.eq_ignore_ascii_case("json") => ConfigFormat::Json,
+            _ => ConfigFormat::Legacy,
+        }
+    }
+}
+
+// One setting as it was written in the config file
+pub struct ConfigEntry {
+    pub key: String,
+    pub value: Value,
+    pub line: Option<usize>,
+}
+
+// Settings read from a config file and everything wrong with it
+pub struct LoadedConfig {
+    pub path: PathBuf,
+    pub sim_settings: SimSettings,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl LoadedConfig {
+    // Whether the simulation has to refuse to start
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|diagnostic| diagnostic.severity == Severity::Error)
+    }
+
+    // Every diagnostic on its own line under the name of the file
+    pub fn report(&self) -> String {
+        let mut report = format!("Problems in config file {}:", self.path.display());
+        for diagnostic in &self.diagnostics {
+            report.push_str(&format!("\n  {}", diagnostic));
+        }
+        report
+    }
+
+    // Print the diagnostics, if there are any
+    pub fn print_report(&self) {
+        if !self.diagnostics.is_empty() {
+            println!("{}", self.report());
+        }
+    }
+}
+
+// Load the config file from the command line and apply the settings given there
+pub fn load(args: &ConfigArgs) -> LoadedConfig {
+    let mut config = match &args.config {
+        Some(path) => read_config(path, true),
+        None => read_config(&default_config_path(), false),
+    };
+
+    // Settings from the command line come after the file so they replace it
+    let defaults = serde_json::to_value(SimSettings::default()).unwrap_or_default();
+    for arg in &args.overrides {
+        match arg.split_once('=') {
+            Some((key, raw)) => config.entries.push(legacy::text_entry(&defaults, key, raw, None)),
+            None => config.diagnostics.push(Diagnostic::error(None, format!("--set expects key=value, got {}.", arg))),
+        }
+    }
+    if let Some(out) = &args.out {
+        config.entries.push(ConfigEntry { key: "csv_location".to_string(), value: Value::from(out.as_str()), line: None });
+    }
+    if let Some(seed) = args.seed {
+        config.entries.push(ConfigEntry { key: "seed".to_string(), value: Value::from(seed), line: None });
+    }
+
+    let (sim_settings, mut diagnostics) = apply_entries(config.entries);
+    config.diagnostics.append(&mut diagnostics);
+
+    LoadedConfig { path: config.path, sim_settings, diagnostics: config.diagnostics }
+}
+
+// First default config file that exists, config.txt if there is none
+pub fn default_config_path() -> PathBuf {
+    DEFAULT_CONFIG_PATHS
+        .iter()
+        .map(PathBuf::from)
+        .find(|path| path.exists())
+        .unwrap_or_else(|| PathBuf::from("config.txt"))
+}
+
+// Settings of a config file before they are applied
+struct ConfigFile {
+    path: PathBuf,
+    entries: Vec<ConfigEntry>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+// Read the settings of a config file, a missing file is only an error when it was asked for
+fn read_config(path: &Path, required: bool) -> ConfigFile {
+    let mut config = ConfigFile { path: path.to_path_buf(), entries: vec![], diagnostics: vec![] };
+
+    match fs::read_to_string(path) {
+        Ok(text) => match read_entries(&text, ConfigFormat::from_path(path)) {
+            Ok(entries) => config.entries = entries,
+            Err(diagnostic) => config.diagnostics.push(diagnostic),
+        },
+        Err(err) => {
+            let message = format!("Could not read config file {}: {}.", path.display(), err);
+            let diagnostic = if required { Diagnostic::error(None, message) } else { Diagnostic::warning(None, format!("{} Using default settings.", message)) };
+            config.diagnostics.push(diagnostic);
+        }
+    }
+
+    config
+}
+
+// Split a config file into its settings, failing if the syntax is broken
+fn read_entries(text: &str, format: ConfigFormat) -> Result<Vec<ConfigEntry>, Diagnostic> {
+    let table = match format {
+        ConfigFormat::Toml => toml::from_str(text).map_err(|err| Diagnostic::error(None, err.to_string()))?,
+        // Read through ron's own value type, its struct field names can only be read as identifiers
+        ConfigFormat::Ron => ron::from_str::<ron::Value>(text)
+            .map_err(|err| Diagnostic::error(None, err.to_string()))
+            .and_then(|value| serde_json::to_value(value).map_err(|err| Diagnostic::error(None, err.to_string())))?,
+        ConfigFormat::Json => serde_json::from_str(text).map_err(|err| Diagnostic::error(None, err.to_string()))?,
+        ConfigFormat::Legacy => return Ok(legacy::read_entries(text)),
+    };
+
+    match table {
+        Value::Object(table) => {
+            let defaults = serde_json::to_value(SimSettings::default()).unwrap_or_default();
+            let lines = lines::setting_lines(text, format);
+            let mut entries = vec![];
+            flatten_entries(table, "", &lines, &defaults, &mut entries);
+            Ok(entries)
+        }
+        _ => Err(Diagnostic::error(None, "The config file must be a table of settings.")),
+    }
+}
+
+// Split a table into an entry per setting, going into groups of settings such as arena
+// Settings in a group are named with dots, e.g. arena.walls.north
+fn flatten_entries(table: Map<String, Value>, prefix: &str, lines: &HashMap<String, usize>, defaults: &Value, entries: &mut Vec<ConfigEntry>) {
+    for (name, value) in table {
+        let key = if prefix.is_empty() { name } else { format!("{}.{}", prefix, name) };
+
+        match value {
+            Value::Object(group) if defaults.pointer(&setting_pointer(&key)).is_some_and(Value::is_object) => {
+                flatten_entries(group, &key, lines, defaults, entries);
+            }
+            value => {
+                let line = lines::line_of(lines, &key);
+                entries.push(ConfigEntry { key, value, line });
+            }
+        }
+    }
+}
+
+// Put every entry on top of the defaults, skipping the ones that are unknown or do not fit
+fn apply_entries(entries: Vec<ConfigEntry>) -> (SimSettings, Vec<Diagnostic>) {
+    let mut diagnostics = vec![];
+    let mut lines: HashMap<String, usize> = HashMap::new();
+
+    // Start from the defaults as a tree of setting names to values
+    let mut settings = serde_json::to_value(SimSettings::default()).unwrap_or_default();
+
+    for entry in entries {
+        let pointer = setting_pointer(&entry.key);
+        if settings.pointer(&pointer).is_none() {
+            diagnostics.push(Diagnostic::error(entry.line, format!("Unknown setting {}.", entry.key)));
+            continue;
+        }
+
+        // A setting given twice keeps the last value
+        if let (Some(first), Some(line)) = (lines.get(&entry.key), entry.line) {
+            diagnostics.push(Diagnostic::warning(Some(line), format!("{} is already set on line {}, this value replaces it.", entry.key, first)));
+        }
+
+        // Only keep the new value if the settings still deserialize with it
+        let mut updated = settings.clone();
+        if let Some(slot) = updated.pointer_mut(&pointer) {
+            *slot = entry.value;
+        }
+        match deserialize(updated.clone()) {
+            Ok(_) => settings = updated,
+            Err(err) => {
+                diagnostics.push(Diagnostic::error(entry.line, format!("Invalid value for {}: {}.", entry.key, err.to_string().trim_end_matches('.'))));
+                continue;
+            }
+        }
+
+        if let Some(line) = entry.line {
+            lines.insert(entry.key, line);
+        }
+    }
+
+    let sim_settings = deserialize(settings).unwrap_or_default();
+    diagnostics.extend(validation::validate(&sim_settings, |key| lines.get(key).copied()));
+
+    // Report in the order of the file, problems without a line first
+    diagnostics.sort_by_key(|diagnostic| diagnostic.line);
+
+    (sim_settings, diagnostics)
+}
+
+fn deserialize(settings: Value) -> serde_json::Result<SimSettings> {
+    serde_json::from_value(settings)
+}
+
+// Where a setting is in the settings tree, arena.width becomes /arena/width
+pub fn setting_pointer(key: &str) -> String {
+    format!("/{}", key.replace('.', "/"))
+}
+
+// Read a setting through its FromStr impl so every format accepts the same names
+pub fn parse_setting<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    String::deserialize(deserializer)?.parse().map_err(D::Error::custom)
+}
+
+// Same as parse_setting for a setting that can be left unset
+pub fn parse_optional_setting<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        Some(value) => value.parse().map(Some).map_err(D::Error::custom),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnostics_point_at_the_line_of_the_setting() {
+        let text = "fragment_count = 20\n\n[arena]\nwidth = -1\nheight = 3\n";
+        let entries = read_entries(text, ConfigFormat::Toml).unwrap_or_default();
+        let (_, diagnostics) = apply_entries(entries);
+        let lines: Vec<_> = diagnostics.iter().map(|diagnostic| (diagnostic.line, diagnostic.message.as_str())).collect();
+        assert_eq!(lines, [(Some(4), "arena.width must be greater than 0, got -1."), (Some(5), "Unknown setting arena.height.")]);
+    }
+}
@@ -0,0 +1,303 @@
+use std::fmt;
+
+use std::path::Path;
+
+use crate::physics::{arena::ArenaPreset, obstacle, sim_settings::SimSettings, terrain, velocity_model::SpeedModel};
+
+// How serious a problem in the config file is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    // The simulation will not start
+    Error,
+    // The simulation starts but probably not as intended
+    Warning,
+}
+
+// A problem found in the config file, with the line it is on when known
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn error(line: Option<usize>, message: impl Into<String>) -> Self {
+        Diagnostic { severity: Severity::Error, line, message: message.into() }
+    }
+
+    pub fn warning(line: Option<usize>, message: impl Into<String>) -> Self {
+        Diagnostic { severity: Severity::Warning, line, message: message.into() }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        match self.line {
+            Some(line) => write!(f, "{}: line {}: {}", severity, line, self.message),
+            None => write!(f, "{}: {}", severity, self.message),
+        }
+    }
+}
+
+// Check that the settings describe a simulation that can run
+// line_of gives the line a setting was set on, if it was set in the file
+pub fn validate(sim_settings: &SimSettings, line_of: impl Fn(&str) -> Option<usize>) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    let mut error = |key: &str, message: String| diagnostics.push(Diagnostic::error(line_of(key), message));
+
+    if sim_settings.fuse_time < 0.0 {
+        error("fuse_time", format!("fuse_time must not be negative, got {}.", sim_settings.fuse_time));
+    }
+
+    if sim_settings.fragment_count == 0 {
+        error("fragment_count", "fragment_count must be at least 1.".to_string());
+    }
+
+    if !(0.0..=1.0).contains(&sim_settings.restitution) {
+        error("restitution", format!("restitution must be between 0 and 1, got {}.", sim_settings.restitution));
+    }
+
+    if sim_settings.friction < 0.0 {
+        error("friction", format!("friction must not be negative, got {}.", sim_settings.friction));
+    }
+
+    // Every size of the arena has to be positive
+    let arena = &sim_settings.arena;
+    for (key, value) in [
+        ("arena.width", arena.width),
+        ("arena.depth", arena.depth),
+        ("arena.wall_height", arena.wall_height),
+        ("arena.wall_thickness", arena.wall_thickness),
+        ("arena.floor_thickness", arena.floor_thickness),
+    ] {
+        if value <= 0.0 {
+            error(key, format!("{} must be greater than 0, got {}.", key, value));
+        }
+    }
+
+    if arena.tracking_radius < 0.0 {
+        error("arena.tracking_radius", format!("arena.tracking_radius must not be negative, got {}.", arena.tracking_radius));
+    }
+
+    // Every obstacle needs a scene file that can be loaded
+    for (index, obstacle) in arena.obstacles.iter().enumerate() {
+        let path = Path::new(&obstacle.path);
+        if obstacle.path.is_empty() {
+            error("arena.obstacles", format!("Obstacle {} in arena.obstacles has no path.", index + 1));
+        } else if !obstacle::is_supported(path) {
+            error("arena.obstacles", format!("Obstacle {} must be a .gltf, .glb or .obj file.", obstacle.path));
+        } else if !path.is_file() {
+            error("arena.obstacles", format!("Obstacle file {} does not exist.", obstacle.path));
+        }
+
+        if obstacle.scale.to_array().contains(&0.0) {
+            error("arena.obstacles", format!("Obstacle {} has a scale of 0, got {}.", obstacle.name(), obstacle.scale));
+        }
+    }
+
+    // The terrain needs a height map that can be loaded and a positive size
+    let terrain_settings = &arena.terrain;
+    if !terrain_settings.path.is_empty() {
+        let path = Path::new(&terrain_settings.path);
+        if !terrain::is_supported(path) {
+            error("arena.terrain.path", format!("Terrain {} must be a .png or .csv file.", terrain_settings.path));
+        } else if !path.is_file() {
+            error("arena.terrain.path", format!("Terrain file {} does not exist.", terrain_settings.path));
+        }
+    }
+    for (key, value) in [("arena.terrain.cell_size", terrain_settings.cell_size), ("arena.terrain.vertical_scale", terrain_settings.vertical_scale)] {
+        if value <= 0.0 {
+            error(key, format!("{} must be greater than 0, got {}.", key, value));
+        }
+    }
+
+    // The grenade starts above the middle of the floor and has to be below the top of the walls of a room
+    match arena.preset {
+        ArenaPreset::Room if sim_settings.initial_height <= 0.0 || sim_settings.initial_height >= arena.wall_height => {
+            error("initial_height", format!("initial_height must be between 0 and arena.wall_height {} to start inside the room, got {}.", arena.wall_height, sim_settings.initial_height));
+        }
+        ArenaPreset::OpenField if sim_settings.initial_height <= 0.0 => {
+            error("initial_height", format!("initial_height must be greater than 0 to start above the ground, got {}.", sim_settings.initial_height));
+        }
+        _ => {}
+    }
+
+    if sim_settings.casing_mass <= 0.0 {
+        error("casing_mass", format!("casing_mass must be greater than 0, got {}.", sim_settings.casing_mass));
+    }
+
+    if sim_settings.fragment_density <= 0.0 {
+        error("fragment_density", format!("fragment_density must be greater than 0, got {}.", sim_settings.fragment_density));
+    }
+
+    if let Some(explosion_vel) = sim_settings.explosion_vel.filter(|vel| *vel < 0.0) {
+        error("explosion_vel", format!("explosion_vel must not be negative, got {}.", explosion_vel));
+    }
+
+    if let Some(charge_mass) = sim_settings.charge_mass.filter(|mass| *mass <= 0.0) {
+        error("charge_mass", format!("charge_mass must be greater than 0, got {}.", charge_mass));
+    }
+
+    if sim_settings.timestep < 0.0 {
+        error("timestep", format!("timestep must not be negative, got {}.", sim_settings.timestep));
+    }
+
+    if sim_settings.speed_model == SpeedModel::Uniform && sim_settings.speed_min > sim_settings.speed_max {
+        error("speed_min", format!("speed_min {} is greater than speed_max {}.", sim_settings.speed_min, sim_settings.speed_max));
+    }
+
+    // Settings that are allowed but probably a mistake
+    if sim_settings.explosion_vel.is_some() && sim_settings.charge_mass.is_some() {
+        diagnostics.push(Diagnostic::warning(line_of("charge_mass"), "charge_mass is ignored because explosion_vel is set."));
+    }
+
+    if sim_settings.max_sim_time <= 0.0 && sim_settings.max_steps == 0 && sim_settings.rest_velocity <= 0.0 {
+        diagnostics.push(Diagnostic::warning(line_of("max_sim_time"), "max_sim_time, max_steps and rest_velocity are all 0, the simulation only ends when quit."));
+    }
+
+    // Without a floor, fragments that miss the obstacles have to be stopped by the tracking radius
+    if sim_settings.arena.preset == ArenaPreset::None && sim_settings.arena.tracking_radius == 0.0 {
+        diagnostics.push(Diagnostic::warning(line_of("arena.tracking_radius"), "arena.preset is none and arena.tracking_radius is 0, fragments that miss the obstacles fall forever."));
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::prelude::*;
+
+    use crate::physics::obstacle::ObstacleSettings;
+
+    // Messages for the default settings with one change, every setting on line 1
+    fn check(change: impl FnOnce(&mut SimSettings)) -> Vec<(Severity, String)> {
+        let mut sim_settings = SimSettings::default();
+        change(&mut sim_settings);
+        validate(&sim_settings, |_| Some(1)).into_iter().map(|diagnostic| (diagnostic.severity, diagnostic.message)).collect()
+    }
+
+    fn error(message: &str) -> Vec<(Severity, String)> {
+        vec![(Severity::Error, message.to_string())]
+    }
+
+    fn warning(message: &str) -> Vec<(Severity, String)> {
+        vec![(Severity::Warning, message.to_string())]
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        assert!(check(|_| {}).is_empty());
+    }
+
+    #[test]
+    fn diagnostics_have_the_line_of_their_setting() {
+        let diagnostics = validate(&SimSettings { fragment_count: 0, ..default() }, |key| (key == "fragment_count").then_some(12));
+        assert_eq!(diagnostics[0].line, Some(12));
+        assert_eq!(diagnostics[0].to_string(), "error: line 12: fragment_count must be at least 1.");
+    }
+
+    #[test]
+    fn simulation_settings() {
+        assert_eq!(check(|s| s.fuse_time = -1.0), error("fuse_time must not be negative, got -1."));
+        assert_eq!(check(|s| s.fragment_count = 0), error("fragment_count must be at least 1."));
+        assert_eq!(check(|s| s.restitution = 1.5), error("restitution must be between 0 and 1, got 1.5."));
+        assert_eq!(check(|s| s.friction = -1.0), error("friction must not be negative, got -1."));
+        assert_eq!(check(|s| s.timestep = -1.0), error("timestep must not be negative, got -1."));
+    }
+
+    #[test]
+    fn arena_sizes() {
+        assert_eq!(check(|s| s.arena.width = 0.0), error("arena.width must be greater than 0, got 0."));
+        assert_eq!(check(|s| s.arena.depth = -1.0), error("arena.depth must be greater than 0, got -1."));
+        assert_eq!(check(|s| s.arena.wall_height = 0.0)[0], (Severity::Error, "arena.wall_height must be greater than 0, got 0.".to_string()));
+        assert_eq!(check(|s| s.arena.wall_thickness = 0.0), error("arena.wall_thickness must be greater than 0, got 0."));
+        assert_eq!(check(|s| s.arena.floor_thickness = 0.0), error("arena.floor_thickness must be greater than 0, got 0."));
+        assert_eq!(check(|s| s.arena.tracking_radius = -1.0), error("arena.tracking_radius must not be negative, got -1."));
+    }
+
+    #[test]
+    fn obstacles() {
+        let obstacle = |path: &str| ObstacleSettings { path: path.to_string(), ..default() };
+
+        assert_eq!(check(|s| s.arena.obstacles = vec![obstacle("")]), error("Obstacle 1 in arena.obstacles has no path."));
+        assert_eq!(check(|s| s.arena.obstacles = vec![obstacle("rock.stl")]), error("Obstacle rock.stl must be a .gltf, .glb or .obj file."));
+        assert_eq!(check(|s| s.arena.obstacles = vec![obstacle("missing/rock.glb")]), error("Obstacle file missing/rock.glb does not exist."));
+
+        let flat = ObstacleSettings { scale: Vec3::new(1.0, 0.0, 1.0), ..obstacle("missing/rock.glb") };
+        assert_eq!(check(|s| s.arena.obstacles = vec![flat])[1], (Severity::Error, "Obstacle rock has a scale of 0, got [1, 0, 1].".to_string()));
+    }
+
+    #[test]
+    fn terrain() {
+        assert_eq!(check(|s| s.arena.terrain.path = "hill.jpg".to_string()), error("Terrain hill.jpg must be a .png or .csv file."));
+        assert_eq!(check(|s| s.arena.terrain.path = "missing/hill.png".to_string()), error("Terrain file missing/hill.png does not exist."));
+        assert_eq!(check(|s| s.arena.terrain.cell_size = 0.0), error("arena.terrain.cell_size must be greater than 0, got 0."));
+        assert_eq!(check(|s| s.arena.terrain.vertical_scale = -1.0), error("arena.terrain.vertical_scale must be greater than 0, got -1."));
+    }
+
+    #[test]
+    fn initial_height() {
+        assert_eq!(
+            check(|s| s.initial_height = 80.0),
+            error("initial_height must be between 0 and arena.wall_height 75 to start inside the room, got 80.")
+        );
+        assert_eq!(
+            check(|s| {
+                s.arena.preset = ArenaPreset::OpenField;
+                s.initial_height = 0.0;
+            }),
+            error("initial_height must be greater than 0 to start above the ground, got 0.")
+        );
+        assert!(check(|s| {
+            s.arena.preset = ArenaPreset::None;
+            s.initial_height = -10.0;
+        })
+        .is_empty());
+    }
+
+    #[test]
+    fn grenade() {
+        assert_eq!(check(|s| s.casing_mass = 0.0), error("casing_mass must be greater than 0, got 0."));
+        assert_eq!(check(|s| s.fragment_density = 0.0), error("fragment_density must be greater than 0, got 0."));
+        assert_eq!(check(|s| s.explosion_vel = Some(-1.0)), error("explosion_vel must not be negative, got -1."));
+        assert_eq!(check(|s| s.charge_mass = Some(0.0)), error("charge_mass must be greater than 0, got 0."));
+        assert_eq!(
+            check(|s| {
+                s.speed_model = SpeedModel::Uniform;
+                s.speed_min = 600.0;
+            }),
+            error("speed_min 600 is greater than speed_max 560.")
+        );
+    }
+
+    #[test]
+    fn warnings() {
+        assert_eq!(
+            check(|s| {
+                s.explosion_vel = Some(500.0);
+                s.charge_mass = Some(0.1);
+            }),
+            warning("charge_mass is ignored because explosion_vel is set.")
+        );
+        assert_eq!(
+            check(|s| {
+                s.max_sim_time = 0.0;
+                s.rest_velocity = 0.0;
+            }),
+            warning("max_sim_time, max_steps and rest_velocity are all 0, the simulation only ends when quit.")
+        );
+        assert_eq!(
+            check(|s| {
+                s.arena.preset = ArenaPreset::None;
+                s.arena.tracking_radius = 0.0;
+            }),
+            warning("arena.preset is none and arena.tracking_radius is 0, fragments that miss the obstacles fall forever.")
+        );
+    }
+}
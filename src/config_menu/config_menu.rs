@@ -1,4 +1,4 @@
-use crate::AppState;
+use crate::{AppState, cli::ConfigArgs, config, output, physics::{obstacle, sim_settings::SimSettings, terrain::{self, Terrain}}};
 use bevy::{prelude::*, app::AppExit};
 
 pub struct MainMenuPlugin;
@@ -14,6 +14,7 @@ struct MenuMaterials {
     menu: UiColor,
     button: UiColor,
     button_text: Color,
+    error_text: Color,
 }
 
 #[derive(Component)]
@@ -22,10 +23,18 @@ enum MenuButton {
     Quit,
 }
 
+// Text that lists the problems with the config file
+#[derive(Component)]
+struct ConfigMessages;
+
 // Function to handle button being clicked
 fn button_press_system(
+    mut commands: Commands,
     buttons: Query<(&Interaction, &MenuButton), (Changed<Interaction>, With<Button>)>,
     mut app_state: ResMut<State<AppState>>,
+    config_args: Res<ConfigArgs>,
+    mut sim_settings: ResMut<SimSettings>,
+    mut messages: Query<&mut Text, With<ConfigMessages>>,
     mut exit: EventWriter<AppExit>
 ) {
     // Check each button for interaction
@@ -37,6 +46,59 @@ fn button_press_system(
                 // If button is the start button
                 MenuButton::Play => {
 
+                    // Read the config file again so it can be changed between simulations
+                    let config = config::load(&config_args);
+                    config.print_report();
+
+                    // Show everything wrong with the config, only staying in the menu if it has errors
+                    for mut text in messages.iter_mut() {
+                        text.sections[0].value = if config.diagnostics.is_empty() { String::new() } else { config.report() };
+                    }
+                    if config.has_errors() {
+                        continue;
+                    }
+
+                    // Read the terrain and obstacles, staying in the menu if they can not be loaded
+                    let terrain = match terrain::load_terrain(&config.sim_settings) {
+                        Ok(terrain) => terrain,
+                        Err(err) => {
+                            println!("{}", err);
+                            for mut text in messages.iter_mut() {
+                                text.sections[0].value = err.clone();
+                            }
+                            continue;
+                        }
+                    };
+                    let obstacles = match obstacle::load_obstacles(&config.sim_settings) {
+                        Ok(obstacles) => obstacles,
+                        Err(err) => {
+                            println!("{}", err);
+                            for mut text in messages.iter_mut() {
+                                text.sections[0].value = err.clone();
+                            }
+                            continue;
+                        }
+                    };
+
+                    // Open the data file, staying in the menu if it can not be written
+                    let output_file = match output::prepare_output(&config.sim_settings) {
+                        Ok(output_file) => output_file,
+                        Err(err) => {
+                            println!("{}", err);
+                            for mut text in messages.iter_mut() {
+                                text.sections[0].value = err.clone();
+                            }
+                            continue;
+                        }
+                    };
+                    commands.insert_resource(output_file);
+                    commands.insert_resource(obstacles);
+                    match terrain {
+                        Some(terrain) => commands.insert_resource(terrain),
+                        None => commands.remove_resource::<Terrain>(),
+                    }
+                    *sim_settings = config.sim_settings;
+
                     // Update app state to simulation
                     app_state
                         .set(AppState::LiveSim)
@@ -61,6 +123,7 @@ impl Plugin for MainMenuPlugin {
                 menu: Color::rgb(0.15, 0.15, 0.15).into(),
                 button: Color::rgb(0.15, 0.15, 0.15).into(),
                 button_text: Color::WHITE,
+                error_text: Color::rgb(1.0, 0.4, 0.4),
             })
             // Add button handling system
             .add_system(button_press_system)
@@ -133,7 +196,7 @@ fn button(materials: &Res<MenuMaterials>) -> ButtonBundle {
 
 // Add text to button
 fn button_text(asset_server: &Res<AssetServer>, materials: &Res<MenuMaterials>, label: &str) -> TextBundle {
-    return TextBundle {
+    TextBundle {
         style: Style {
             margin: Rect::all(Val::Px(10.0)),
             ..Default::default()
@@ -143,12 +206,34 @@ fn button_text(asset_server: &Res<AssetServer>, materials: &Res<MenuMaterials>,
             TextStyle {
                 font: asset_server.load("Gidole-Regular.ttf"),
                 font_size: 30.0,
-                color: materials.button_text.clone(),
+                color: materials.button_text,
+            },
+            Default::default(),
+        ),
+        ..Default::default()
+    }
+}
+
+// Add text for config problems, empty until the config is read
+fn config_messages(asset_server: &Res<AssetServer>, materials: &Res<MenuMaterials>) -> TextBundle {
+    TextBundle {
+        style: Style {
+            margin: Rect::all(Val::Px(10.0)),
+            // Wrap long messages inside the menu
+            max_size: Size::new(Val::Px(360.0), Val::Undefined),
+            ..Default::default()
+        },
+        text: Text::with_section(
+            "",
+            TextStyle {
+                font: asset_server.load("Gidole-Regular.ttf"),
+                font_size: 16.0,
+                color: materials.error_text,
             },
             Default::default(),
         ),
         ..Default::default()
-    };
+    }
 }
 
 // Initialize menu
@@ -183,6 +268,8 @@ fn setup(
                                     parent.spawn_bundle(button_text(&asset_server, &materials, "Quit"));
                                 })
                                 .insert(MenuButton::Quit);
+                            parent.spawn_bundle(config_messages(&asset_server, &materials))
+                                .insert(ConfigMessages);
                         });
                 });
         })
@@ -1 +1,2 @@
+#[allow(clippy::module_inception)]
 pub mod config_menu;
\ No newline at end of file
@@ -0,0 +1,60 @@
+use bevy::{prelude::*, app::{AppExit, ScheduleRunnerSettings}, asset::AssetPlugin, scene::Scene, transform::TransformPlugin};
+use std::{sync::{Arc, atomic::{AtomicI32, Ordering}}, time::Duration};
+
+use crate::{output::data_file::DataFile, physics::sim_end::SimulationEnded};
+
+// Create a plugin that replaces DefaultPlugins when running without a window
+pub struct HeadlessPlugin;
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            // Step as fast as possible, simulated time comes from the fixed timestep
+            .insert_resource(ScheduleRunnerSettings::run_loop(Duration::ZERO))
+
+            // Add time, task pools and the schedule runner
+            .add_plugins(MinimalPlugins)
+
+            // Keep global transforms in sync for the physics engine
+            .add_plugin(TransformPlugin)
+
+            // The physics engine expects mesh and scene storage even when nothing is rendered
+            .add_plugin(AssetPlugin)
+            .add_asset::<Mesh>()
+            .add_asset::<Scene>()
+
+            // Exit once the simulation is over
+            .add_system(finish_simulation);
+    }
+}
+
+// Exit code of a headless run, shared with main after the app stops
+#[derive(Clone, Default)]
+pub struct ExitCode(Arc<AtomicI32>);
+
+impl ExitCode {
+    // Mark the run as failed, e.g. when it could not be set up as configured
+    pub fn fail(&self) {
+        self.0.store(1, Ordering::SeqCst);
+    }
+
+    // Get the code to exit the process with
+    pub fn get(&self) -> i32 {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+// Exit once the fragments have come to an end
+fn finish_simulation(
+    mut ended: EventReader<SimulationEnded>,
+    data_file: Option<Res<DataFile>>,
+    mut exit: EventWriter<AppExit>
+) {
+    if let Some(end) = ended.iter().next() {
+        println!("Simulation ended after {:.3}s and {} steps: {}.", end.elapsed, end.steps, end.reason);
+        if let Some(data_file) = data_file {
+            println!("Data written to {}.", data_file.0.display());
+        }
+        exit.send(AppExit);
+    }
+}
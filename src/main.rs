@@ -1,18 +1,208 @@
+// Bevy systems routinely take many resources and queries
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
+
 use bevy::prelude::*;
+use clap::Parser;
+
+use crate::{cli::{Cli, Command, ConfigArgs, ReplayArgs, ValidateArgs}, physics::{obstacle, terrain}};
 
 mod physics;
 mod config_menu;
+mod headless;
+mod output;
+mod config;
+mod cli;
+mod replay;
+mod batch;
+
 fn main() {
-    App::new()
-        .add_state(AppState::MainMenu)
+    // Parse the command line once
+    let cli = Cli::parse();
+
+    let exit_code = match cli.command {
+        Some(Command::Gui(args)) => run_gui(args, false),
+        Some(Command::Run(args)) if args.headless => run_headless(args.config),
+        Some(Command::Run(args)) => run_gui(args.config, true),
+        Some(Command::Batch(args)) => batch::run_batch(args),
+        Some(Command::Validate(args)) => validate(args),
+        Some(Command::Replay(args)) => run_replay(args),
+        // Without a subcommand keep the original behaviour of the flags
+        None if cli.run.headless => run_headless(cli.run.config),
+        None => run_gui(cli.run.config, false),
+    };
+
+    // Report how the run went
+    std::process::exit(exit_code);
+}
+
+// Open a window with the menu, or with a simulation that starts right away
+fn run_gui(args: ConfigArgs, start: bool) -> i32 {
+    let mut app = App::new();
+
+    if start {
+        // Read the config up front so a bad config stops the run before the window opens
+        let config = config::load(&args);
+        config.print_report();
+        if config.has_errors() {
+            return 1;
+        }
+
+        // Read the terrain and obstacles too, a run is not started in the wrong arena
+        let terrain = match terrain::load_terrain(&config.sim_settings) {
+            Ok(terrain) => terrain,
+            Err(err) => {
+                println!("{}", err);
+                return 1;
+            }
+        };
+        let obstacles = match obstacle::load_obstacles(&config.sim_settings) {
+            Ok(obstacles) => obstacles,
+            Err(err) => {
+                println!("{}", err);
+                return 1;
+            }
+        };
+
+        // Open the data file too, a run that could not save its data is not started
+        let output_file = match output::prepare_output(&config.sim_settings) {
+            Ok(output_file) => output_file,
+            Err(err) => {
+                println!("{}", err);
+                return 1;
+            }
+        };
+        app.insert_resource(config.sim_settings).insert_resource(output_file).insert_resource(obstacles).add_state(AppState::LiveSim);
+        if let Some(terrain) = terrain {
+            app.insert_resource(terrain);
+        }
+    } else {
+        app.add_state(AppState::MainMenu);
+    }
+
+    app
+        // The menu reads the config again every time a simulation is started
+        .insert_resource(args)
         .add_plugins(DefaultPlugins)
         .add_plugin(config_menu::config_menu::MainMenuPlugin)
-        .add_plugin(physics::PhyiscsSimPlugin)
+        .add_plugin(physics::PhyiscsSimPlugin { headless: false })
         .run();
+
+    0
+}
+
+// Run a single simulation without a window
+fn run_headless(args: ConfigArgs) -> i32 {
+    // Read the config up front so a bad config stops the run before anything is simulated
+    let config = config::load(&args);
+    config.print_report();
+    if config.has_errors() {
+        return 1;
+    }
+
+    // Read the terrain and obstacles too, a run is not started in the wrong arena
+    let terrain = match terrain::load_terrain(&config.sim_settings) {
+        Ok(terrain) => terrain,
+        Err(err) => {
+            println!("{}", err);
+            return 1;
+        }
+    };
+    let obstacles = match obstacle::load_obstacles(&config.sim_settings) {
+        Ok(obstacles) => obstacles,
+        Err(err) => {
+            println!("{}", err);
+            return 1;
+        }
+    };
+
+    // Open the data file too, a run that could not save its data is not started
+    let output_file = match output::prepare_output(&config.sim_settings) {
+        Ok(output_file) => output_file,
+        Err(err) => {
+            println!("{}", err);
+            return 1;
+        }
+    };
+
+    // Shared exit code that outlives the app
+    let exit_code = headless::ExitCode::default();
+
+    let mut app = App::new();
+    if let Some(terrain) = terrain {
+        app.insert_resource(terrain);
+    }
+
+    app
+        .insert_resource(config.sim_settings)
+        .insert_resource(output_file)
+        .insert_resource(obstacles)
+        // Skip the menu and go straight into the simulation
+        .add_state(AppState::LiveSim)
+        .insert_resource(exit_code.clone())
+        .add_plugin(headless::HeadlessPlugin)
+        .add_plugin(physics::PhyiscsSimPlugin { headless: true })
+        .run();
+
+    exit_code.get()
+}
+
+// Check a config file without simulating
+fn validate(args: ValidateArgs) -> i32 {
+    let config = config::load(&ConfigArgs { config: Some(args.config), overrides: args.overrides, ..default() });
+    config.print_report();
+
+    if config.has_errors() {
+        return 1;
+    }
+    println!("{} is valid.", config.path.display());
+    0
+}
+
+// Play back a data file in a window
+fn run_replay(args: ReplayArgs) -> i32 {
+    let replay = match replay::load_replay(&args.data) {
+        Ok(replay) => replay,
+        Err(err) => {
+            println!("Could not read data file {}: {}", args.data.display(), err);
+            return 1;
+        }
+    };
+
+    // Show the arena the fragments were simulated in
+    let sim_settings = replay::load_settings(&args.data);
+    let terrain = match terrain::load_terrain(&sim_settings) {
+        Ok(terrain) => terrain,
+        Err(err) => {
+            println!("{}", err);
+            return 1;
+        }
+    };
+    let obstacles = match obstacle::load_obstacles(&sim_settings) {
+        Ok(obstacles) => obstacles,
+        Err(err) => {
+            println!("{}", err);
+            return 1;
+        }
+    };
+
+    let mut app = App::new();
+    if let Some(terrain) = terrain {
+        app.insert_resource(terrain);
+    }
+
+    app
+        .insert_resource(sim_settings)
+        .insert_resource(obstacles)
+        .insert_resource(replay)
+        .add_plugins(DefaultPlugins)
+        .add_plugin(replay::ReplayPlugin { speed: args.speed })
+        .run();
+
+    0
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum AppState {
     MainMenu,
     LiveSim,
-}
\ No newline at end of file
+}
@@ -0,0 +1,155 @@
+use csv::{Writer, WriterBuilder};
+use serde::Serialize;
+use std::{fs::File, str::FromStr};
+
+use super::{OutputResult, SampleEvent, StepRecord, TrajectoryWriter};
+
+// Column names of the long layout
+const LONG_HEADER: [&str; 14] = ["sim_time", "fragment_id", "event", "x", "y", "z", "vx", "vy", "vz", "speed", "mass", "size", "seed", "hit"];
+
+// Size of the write buffer in bytes
+const BUFFER_CAPACITY: usize = 1 << 16;
+
+// How rows are laid out in the csv file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputLayout {
+    // One row per fragment per update with a header, readable by any analysis tool
+    Long,
+    // One row per update with every fragment packed into a cell, the original format
+    Legacy,
+}
+
+impl FromStr for OutputLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "long" => Ok(OutputLayout::Long),
+            "legacy" => Ok(OutputLayout::Legacy),
+            other => Err(format!("Unknown output_layout {}, expected long or legacy.", other)),
+        }
+    }
+}
+
+// Csv file kept open for the whole simulation
+pub struct CsvOutput {
+    wtr: Writer<File>,
+    layout: OutputLayout,
+    seed: u64,
+}
+
+impl CsvOutput {
+    // Start the file with a header, the legacy layout has none
+    pub fn create(file: File, layout: OutputLayout, seed: u64, has_data: bool) -> OutputResult<Self> {
+        // Rows of the legacy layout change length as fragments are removed
+        let mut wtr = WriterBuilder::new()
+            .flexible(true)
+            .buffer_capacity(BUFFER_CAPACITY)
+            .from_writer(file);
+
+        match layout {
+            // An appended run continues under the header that is already there
+            OutputLayout::Long if has_data => {}
+            OutputLayout::Long => wtr.write_record(LONG_HEADER)?,
+            OutputLayout::Legacy => {}
+        }
+
+        Ok(CsvOutput { wtr, layout, seed })
+    }
+}
+
+impl TrajectoryWriter for CsvOutput {
+    // Write every fragment of one step
+    fn write(&mut self, step: &StepRecord) -> OutputResult<()> {
+        match self.layout {
+            OutputLayout::Long => {
+                // Write one row for every fragment
+                for sample in &step.samples {
+                    self.wtr.write_record([
+                        step.sim_time.to_string(),
+                        sample.fragment_id.to_string(),
+                        sample.event.to_string(),
+                        sample.position.x.to_string(),
+                        sample.position.y.to_string(),
+                        sample.position.z.to_string(),
+                        sample.velocity.x.to_string(),
+                        sample.velocity.y.to_string(),
+                        sample.velocity.z.to_string(),
+                        sample.velocity.length().to_string(),
+                        sample.mass.to_string(),
+                        sample.size.to_string(),
+                        self.seed.to_string(),
+                        sample.hit.clone().unwrap_or_default(),
+                    ])?;
+                }
+            }
+            OutputLayout::Legacy => {
+                // Impacts, rests and escapes are only logged in the long layout
+                let samples: Vec<_> = step.samples.iter().filter(|sample| matches!(sample.event, SampleEvent::Launch | SampleEvent::Sample)).collect();
+                if samples.is_empty() {
+                    return Ok(());
+                }
+
+                // Create blank vector to store row data
+                let mut record = vec![];
+
+                // Add the time since last update
+                record.push(format!("{}", step.frame_millis));
+
+                // Store the pos and vel of every fragment
+                for sample in samples {
+                    record.push(format!("XPos:{}|Ypos:{}|Zpos:{}|Xvel:{}|Yvel:{}|Zvel:{}", sample.position.x, sample.position.y, sample.position.z, sample.velocity.x, sample.velocity.y, sample.velocity.z));
+                }
+
+                // Write to file
+                self.wtr.write_record(record)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Flush everything left in the buffer
+    fn finish(mut self: Box<Self>) -> OutputResult<()> {
+        self.wtr.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::FragmentSample;
+    use bevy::prelude::Vec3;
+    use std::{env, fs, process};
+
+    fn sample(fragment_id: u32, event: SampleEvent, hit: Option<&str>) -> FragmentSample {
+        FragmentSample {
+            fragment_id,
+            event,
+            position: Vec3::new(1.0, 2.5, -3.0),
+            velocity: Vec3::new(0.5, -1.0, 4.0),
+            mass: 0.002,
+            size: 0.01,
+            hit: hit.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn legacy_layout_is_the_original_format() {
+        let path = env::temp_dir().join(format!("frag-sim-legacy-{}.csv", process::id()));
+        let mut output = Box::new(CsvOutput::create(File::create(&path).unwrap(), OutputLayout::Legacy, 7, false).unwrap());
+
+        output.write(&StepRecord { sim_time: 0.0, frame_millis: 16, samples: vec![sample(0, SampleEvent::Launch, None), sample(1, SampleEvent::Launch, None)] }).unwrap();
+        output.write(&StepRecord { sim_time: 0.1, frame_millis: 17, samples: vec![sample(0, SampleEvent::Sample, None), sample(0, SampleEvent::Impact, Some("floor"))] }).unwrap();
+        output.write(&StepRecord { sim_time: 0.2, frame_millis: 15, samples: vec![sample(1, SampleEvent::Rest, None)] }).unwrap();
+        output.finish().unwrap();
+
+        // No header or seed row, only the sampled fragments of every step
+        let cell = "XPos:1|Ypos:2.5|Zpos:-3|Xvel:0.5|Yvel:-1|Zvel:4";
+        assert_eq!(fs::read_to_string(&path).unwrap(), format!("16,{0},{0}\n17,{0}\n", cell));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
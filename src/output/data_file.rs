@@ -0,0 +1,192 @@
+use chrono::Local;
+use serde::Serialize;
+use std::{fs::{self, File, OpenOptions}, io::{self, ErrorKind}, path::{Path, PathBuf}, str::FromStr};
+
+use crate::physics::sim_settings::SimSettings;
+
+use super::{OutputFormat, OutputResult};
+
+// Highest number tried by auto_increment before giving up
+const MAX_RUN_NUMBER: u32 = 999;
+
+// What to do when the data file already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputMode {
+    // Replace the existing file
+    Overwrite,
+    // Add the new run to the end of the existing file
+    Append,
+    // Refuse to run instead of touching the existing file
+    FailIfExists,
+    // Write to the first free numbered name, data.csv becomes data_001.csv, data_002.csv, ...
+    AutoIncrement,
+}
+
+impl FromStr for OutputMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "overwrite" => Ok(OutputMode::Overwrite),
+            "append" => Ok(OutputMode::Append),
+            "fail_if_exists" => Ok(OutputMode::FailIfExists),
+            "auto_increment" => Ok(OutputMode::AutoIncrement),
+            other => Err(format!("Unknown output_mode {}, expected overwrite, append, fail_if_exists or auto_increment.", other)),
+        }
+    }
+}
+
+// Path the data file of the current run was written to
+pub struct DataFile(pub PathBuf);
+
+// Data file opened before the simulation starts, so a run that can not save its data never starts
+pub struct OutputFile {
+    pub path: PathBuf,
+    pub file: File,
+    // The file already had data in it when appending
+    pub has_data: bool,
+}
+
+impl OutputFile {
+    // Pick the path from SimSettings and open it as output_mode asks
+    pub fn open(sim_settings: &SimSettings) -> OutputResult<Self> {
+        // The parquet footer has to be at the end of the file, so nothing can be added after it
+        if OutputFormat::from_settings(sim_settings) == OutputFormat::Parquet && sim_settings.output_mode == OutputMode::Append {
+            return Err("parquet files can not be appended to, pick another output_mode".into());
+        }
+
+        let path = resolve_data_path(sim_settings)?;
+        let (path, file, has_data) = open_data_file(&path, sim_settings.output_mode)?;
+        Ok(OutputFile { path, file, has_data })
+    }
+}
+
+// Work out where this run's data goes from csv_location and run_directories
+pub fn resolve_data_path(sim_settings: &SimSettings) -> OutputResult<PathBuf> {
+    let mut path = PathBuf::from(&sim_settings.csv_location);
+
+    // Put each run in its own directory named after the time it started
+    if sim_settings.run_directories {
+        let file_name = path.file_name().ok_or("csv_location has no file name")?.to_owned();
+        let parent = path.parent().unwrap_or_else(|| Path::new(""));
+        let run_dir = parent.join(format!("run_{}", Local::now().format("%Y%m%d_%H%M%S")));
+        fs::create_dir_all(&run_dir)?;
+        path = run_dir.join(file_name);
+    }
+
+    Ok(path)
+}
+
+// Open the data file as output_mode asks, returns the path it ended up at, the file and whether it already has data in it
+pub fn open_data_file(path: &Path, mode: OutputMode) -> OutputResult<(PathBuf, File, bool)> {
+    match mode {
+        OutputMode::Overwrite => {
+            if path.exists() {
+                println!("Overwriting {}.", path.display());
+            }
+            Ok((path.to_path_buf(), File::create(path)?, false))
+        }
+        OutputMode::Append => {
+            let file = OpenOptions::new().append(true).create(true).open(path)?;
+            let has_data = file.metadata()?.len() > 0;
+            Ok((path.to_path_buf(), file, has_data))
+        }
+        OutputMode::FailIfExists => match create_new(path) {
+            Ok(file) => Ok((path.to_path_buf(), file, false)),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                Err(format!("{} already exists and output_mode is fail_if_exists", path.display()).into())
+            }
+            Err(err) => Err(err.into()),
+        },
+        OutputMode::AutoIncrement => {
+            let (path, file) = create_numbered(path)?;
+            Ok((path, file, false))
+        }
+    }
+}
+
+// Create a file only if nothing is there yet, checked and created in one step so another process can not slip a file in between
+fn create_new(path: &Path) -> io::Result<File> {
+    OpenOptions::new().write(true).create_new(true).open(path)
+}
+
+// Create the first numbered file that does not exist yet
+pub fn create_numbered(path: &Path) -> OutputResult<(PathBuf, File)> {
+    for number in 1..=MAX_RUN_NUMBER {
+        let numbered = numbered_path(path, number);
+        match create_new(&numbered) {
+            Ok(file) => return Ok((numbered, file)),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Err("no free numbered file name left".into())
+}
+
+// Add a zero padded number to the file name, data.csv becomes data_001.csv
+pub fn numbered_path(path: &Path, number: u32) -> PathBuf {
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{}_{:03}.{}", stem, number, ext.to_string_lossy()),
+        None => format!("{}_{:03}", stem, number),
+    };
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    // Empty directory for one test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("frag-sim-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn numbered_paths() {
+        assert_eq!(numbered_path(Path::new("out/data.csv"), 1), PathBuf::from("out/data_001.csv"));
+        assert_eq!(numbered_path(Path::new("data.parquet"), 42), PathBuf::from("data_042.parquet"));
+        assert_eq!(numbered_path(Path::new("data"), 999), PathBuf::from("data_999"));
+    }
+
+    #[test]
+    fn create_numbered_skips_existing_files() {
+        let dir = test_dir("numbered");
+        let path = dir.join("data.csv");
+        fs::write(dir.join("data_001.csv"), "kept").unwrap();
+        fs::write(dir.join("data_003.csv"), "kept").unwrap();
+
+        let (first, _) = create_numbered(&path).unwrap();
+        let (second, _) = create_numbered(&path).unwrap();
+        assert_eq!(first, dir.join("data_002.csv"));
+        assert_eq!(second, dir.join("data_004.csv"));
+        assert_eq!(fs::read_to_string(dir.join("data_001.csv")).unwrap(), "kept");
+        assert!(!path.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn existing_files_by_mode() {
+        let dir = test_dir("modes");
+        let path = dir.join("data.csv");
+        fs::write(&path, "earlier run").unwrap();
+
+        assert!(open_data_file(&path, OutputMode::FailIfExists).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "earlier run");
+
+        let (_, _, has_data) = open_data_file(&path, OutputMode::Append).unwrap();
+        assert!(has_data);
+
+        let (_, _, has_data) = open_data_file(&path, OutputMode::Overwrite).unwrap();
+        assert!(!has_data);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,123 @@
+use bevy::math::Vec3;
+use serde::Serialize;
+use std::{fs::File, io::{BufWriter, Write}};
+
+use crate::physics::{grenade::GrenadeData, sim_end::{EndReason, SimulationEnded}, sim_settings::SimSettings};
+
+use super::{Detonation, FragmentSample, OutputResult, SampleEvent, StepRecord, TrajectoryWriter};
+
+// Size of the write buffer in bytes
+const BUFFER_CAPACITY: usize = 1 << 16;
+
+// One line of the file, tagged with its type in the "event" field
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum JsonEvent<'a> {
+    SimStart {
+        version: &'a str,
+        seed: u64,
+        settings: &'a SimSettings,
+    },
+    Detonation(Detonation),
+    FragmentSpawned(FragmentRecord),
+    Sample(FragmentRecord),
+    Impact(FragmentRecord),
+    FragmentRest(FragmentRecord),
+    FragmentEscaped(FragmentRecord),
+    SimEnd {
+        reason: EndReason,
+        sim_time: f32,
+        steps: u32,
+    },
+}
+
+// State of one fragment at one point in simulated time
+#[derive(Serialize)]
+struct FragmentRecord {
+    sim_time: f32,
+    fragment_id: u32,
+    position: Vec3,
+    velocity: Vec3,
+    speed: f32,
+    mass: f32,
+    size: f32,
+    // Only on impacts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hit: Option<String>,
+}
+
+impl FragmentRecord {
+    fn new(sim_time: f32, sample: &FragmentSample) -> Self {
+        FragmentRecord {
+            sim_time,
+            fragment_id: sample.fragment_id,
+            position: sample.position,
+            velocity: sample.velocity,
+            speed: sample.velocity.length(),
+            mass: sample.mass,
+            size: sample.size,
+            hit: sample.hit.clone(),
+        }
+    }
+}
+
+// JSON Lines file with one typed event per line, kept open for the whole simulation
+pub struct JsonlOutput {
+    out: BufWriter<File>,
+}
+
+impl JsonlOutput {
+    // Start writing events to the file
+    pub fn create(file: File) -> JsonlOutput {
+        JsonlOutput { out: BufWriter::with_capacity(BUFFER_CAPACITY, file) }
+    }
+
+    // Write an event on its own line
+    fn write_event(&mut self, event: &JsonEvent) -> OutputResult<()> {
+        serde_json::to_writer(&mut self.out, event)?;
+        self.out.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+impl TrajectoryWriter for JsonlOutput {
+    fn start(&mut self, sim_settings: &SimSettings, seed: u64) -> OutputResult<()> {
+        self.write_event(&JsonEvent::SimStart {
+            version: env!("CARGO_PKG_VERSION"),
+            seed,
+            settings: sim_settings,
+        })
+    }
+
+    fn detonation(&mut self, grenade_data: &GrenadeData) -> OutputResult<()> {
+        self.write_event(&JsonEvent::Detonation(Detonation::from(grenade_data)))
+    }
+
+    fn write(&mut self, step: &StepRecord) -> OutputResult<()> {
+        for sample in &step.samples {
+            let record = FragmentRecord::new(step.sim_time, sample);
+            let event = match sample.event {
+                SampleEvent::Launch => JsonEvent::FragmentSpawned(record),
+                SampleEvent::Sample => JsonEvent::Sample(record),
+                SampleEvent::Impact => JsonEvent::Impact(record),
+                SampleEvent::Rest => JsonEvent::FragmentRest(record),
+                SampleEvent::Escaped => JsonEvent::FragmentEscaped(record),
+            };
+            self.write_event(&event)?;
+        }
+        Ok(())
+    }
+
+    fn end(&mut self, ended: &SimulationEnded) -> OutputResult<()> {
+        self.write_event(&JsonEvent::SimEnd {
+            reason: ended.reason,
+            sim_time: ended.elapsed,
+            steps: ended.steps,
+        })
+    }
+
+    fn finish(mut self: Box<Self>) -> OutputResult<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,193 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use serde::Serialize;
+use serde_json::Value;
+use std::{fs::{self, File}, io::{BufWriter, Write}, path::{Path, PathBuf}, time::Instant};
+
+use crate::physics::{arena::ArenaSettings, fragment::{EscapedFragments, Fragment}, grenade::GrenadeData, sim_end::{EndReason, SimulationEnded}, sim_settings::SimSettings, terrain::{ground_height, Terrain}};
+
+use super::{data_file::OutputMode, Detonation, OutputResult};
+
+// Wall-clock time the simulation was started
+pub struct RunTimer(Instant);
+
+// Start timing the run when the simulation is started
+pub fn start_run_timer(mut commands: Commands) {
+    commands.insert_resource(RunTimer(Instant::now()));
+}
+
+// Record of how a data file was produced, written next to it when the run is over
+#[derive(Serialize)]
+pub struct RunMetadata {
+    version: &'static str,
+    data_file: PathBuf,
+    seed: u64,
+    timestep: f32,
+    substeps: usize,
+    fragment_count: u32,
+    detonation: Detonation,
+    end_reason: Option<EndReason>,
+    sim_time: Option<f32>,
+    steps: Option<u32>,
+    wall_clock_seconds: f64,
+    fragments: Option<FragmentStats>,
+    // Appended runs share the data file, so its metadata file lists every run in it
+    #[serde(skip)]
+    appended: bool,
+    // Whether runs were already in the data file, their metadata is kept
+    #[serde(skip)]
+    earlier_runs: bool,
+}
+
+// Where the fragments ended up when the data file was closed
+#[derive(Serialize)]
+pub struct FragmentStats {
+    count: u32,
+    // Fragments slower than rest_velocity
+    at_rest: u32,
+    // Removed for leaving the tracking radius, not part of the other values
+    escaped: u32,
+    // Fragments outside the arena
+    out_of_bounds: u32,
+    // Horizontal distance from the detonation point
+    mean_distance: f32,
+    max_distance: f32,
+    mean_speed: f32,
+}
+
+// Layout of the metadata file, the settings are written last since they are the longest part
+#[derive(Serialize)]
+struct MetadataFile<'a> {
+    #[serde(flatten)]
+    run: &'a RunMetadata,
+    settings: &'a SimSettings,
+}
+
+impl RunMetadata {
+    // Capture the settings and detonation when the data file is opened
+    pub fn new(sim_settings: &SimSettings, data_file: &Path, seed: u64, grenade_data: &GrenadeData, has_data: bool) -> Self {
+        let appended = sim_settings.output_mode == OutputMode::Append;
+        RunMetadata {
+            version: env!("CARGO_PKG_VERSION"),
+            data_file: data_file.to_path_buf(),
+            seed,
+            timestep: sim_settings.timestep,
+            substeps: sim_settings.substeps,
+            fragment_count: sim_settings.fragment_count,
+            detonation: Detonation::from(grenade_data),
+            end_reason: None,
+            sim_time: None,
+            steps: None,
+            wall_clock_seconds: 0.0,
+            fragments: None,
+            appended,
+            earlier_runs: appended && has_data,
+        }
+    }
+
+    // Measure the fragments that are still in the world, left empty if there are none and none escaped
+    pub fn measure_fragments(&mut self, world: &mut World, rest_velocity: f32, arena: &ArenaSettings) {
+        let escaped = world.get_resource::<EscapedFragments>().map_or(0, |escaped| escaped.0);
+        let origin = self.detonation.position;
+        let mut fragments = world.query_filtered::<(&Transform, &Velocity), With<Fragment>>();
+        let terrain = world.get_resource::<Terrain>();
+        let mut out_of_bounds = 0;
+        let (distances, speeds): (Vec<f32>, Vec<f32>) = fragments
+            .iter(world)
+            .map(|(transform, vel)| {
+                if arena.is_outside(transform.translation, ground_height(terrain, transform.translation)) {
+                    out_of_bounds += 1;
+                }
+                let offset = transform.translation - origin;
+                (Vec2::new(offset.x, offset.z).length(), vel.linvel.length())
+            })
+            .unzip();
+
+        if distances.is_empty() && escaped == 0 {
+            return;
+        }
+
+        // Every value but escaped is 0 when all fragments escaped
+        let count = distances.len().max(1) as f32;
+        self.fragments = Some(FragmentStats {
+            count: distances.len() as u32,
+            at_rest: speeds.iter().filter(|speed| **speed < rest_velocity).count() as u32,
+            escaped,
+            out_of_bounds,
+            mean_distance: distances.iter().sum::<f32>() / count,
+            max_distance: distances.iter().copied().fold(0.0, f32::max),
+            mean_speed: speeds.iter().sum::<f32>() / count,
+        });
+    }
+
+    // Path of the data file this metadata describes
+    pub fn data_file(&self) -> &Path {
+        &self.data_file
+    }
+
+    // Fill in how the run ended and write the file, the end is left empty if the run was stopped early
+    pub fn write(mut self, sim_settings: &SimSettings, ended: Option<&SimulationEnded>, run_timer: Option<&RunTimer>) -> OutputResult<()> {
+        let path = metadata_path(&self.data_file);
+
+        if let Some(ended) = ended {
+            self.end_reason = Some(ended.reason);
+            self.sim_time = Some(ended.elapsed);
+            self.steps = Some(ended.steps);
+        }
+        if let Some(RunTimer(started)) = run_timer {
+            self.wall_clock_seconds = started.elapsed().as_secs_f64();
+        }
+
+        let metadata = serde_json::to_value(MetadataFile { run: &self, settings: sim_settings })?;
+        let metadata = if self.appended { Value::Array(earlier_runs(&path, self.earlier_runs)?.into_iter().chain([metadata]).collect()) } else { metadata };
+
+        let mut out = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut out, &metadata)?;
+        out.write_all(b"\n")?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+// Metadata of the runs already in an appended data file, one from before runs were listed counts as the first
+// A metadata file that can not be read is left alone instead of losing the runs in it
+fn earlier_runs(path: &Path, earlier_runs: bool) -> OutputResult<Vec<Value>> {
+    if !earlier_runs || !path.exists() {
+        return Ok(vec![]);
+    }
+    match serde_json::from_str(&fs::read_to_string(path)?)? {
+        Value::Array(runs) => Ok(runs),
+        run => Ok(vec![run]),
+    }
+}
+
+// Metadata goes next to the data file, e.g. data.csv gets data.meta.json
+pub fn metadata_path(data_file: &Path) -> PathBuf {
+    data_file.with_extension("meta.json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    #[test]
+    fn appended_runs_keep_the_earlier_metadata() {
+        let path = env::temp_dir().join(format!("frag-sim-appended-{}.meta.json", process::id()));
+
+        // Metadata written by a run that did not append counts as the first run
+        fs::write(&path, r#"{"seed": 1}"#).unwrap();
+        assert_eq!(earlier_runs(&path, true).unwrap(), vec![serde_json::json!({"seed": 1})]);
+
+        fs::write(&path, r#"[{"seed": 1}, {"seed": 2}]"#).unwrap();
+        assert_eq!(earlier_runs(&path, true).unwrap().len(), 2);
+
+        // Metadata of a data file that was started fresh is replaced, a broken one is not
+        assert!(earlier_runs(&path, false).unwrap().is_empty());
+        fs::write(&path, "{").unwrap();
+        assert!(earlier_runs(&path, true).is_err());
+
+        fs::remove_file(&path).unwrap();
+        assert!(earlier_runs(&path, true).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,521 @@
+use bevy::{prelude::*, ecs::system::Command};
+use bevy_rapier3d::prelude::*;
+use serde::Serialize;
+use std::{collections::HashSet, error::Error, fmt, path::{Path, PathBuf}, str::FromStr, sync::mpsc::{self, Sender}, thread::{self, JoinHandle}};
+
+use crate::{
+    headless::ExitCode,
+    AppState,
+    physics::{
+        GrenadeState,
+        grenade::GrenadeData,
+        fragment::{self, Fragment, FragmentEscaped, FragmentId, FragmentProperties},
+        sim_end::{self, SimClock, SimulationEnded},
+        sim_rng::SimRng,
+        sim_settings::SimSettings,
+        sim_setup::SurfaceName,
+    },
+};
+
+use self::{csv_output::CsvOutput, data_file::{DataFile, OutputFile}, jsonl_output::JsonlOutput, metadata::{RunMetadata, RunTimer}, parquet_output::ParquetOutput};
+
+pub mod csv_output;
+pub mod data_file;
+pub mod jsonl_output;
+pub mod metadata;
+pub mod parquet_output;
+
+// Errors from writing data, sendable so the writer thread can report them
+pub type OutputResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+// Create a plugin for exporting fragment data
+pub struct OutputPlugin;
+
+impl Plugin for OutputPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            // When simulation is started note the wall-clock time for the run metadata
+            .add_system_set(SystemSet::on_enter(AppState::LiveSim).with_system(metadata::start_run_timer))
+
+            // Keep rapier's collision events from piling up between updates
+            .add_event::<CollisionEvent>()
+
+            // When entering the fragment part of the simulation open the data file and forget what the last run logged
+            .add_system_set(SystemSet::on_enter(GrenadeState::Fragment).with_system(open_data_writer).with_system(reset_log_state))
+
+            // While in the fragment part of simulation, export path of frag before the clock moves on
+            // Run after escaped fragments are found so their last position is logged before they are removed
+            .add_system_set(
+                SystemSet::on_update(GrenadeState::Fragment)
+                    .with_system(write_fragment_data.before(sim_end::advance_sim_clock).after(fragment::remove_escaped_fragments))
+            )
+
+            // When the simulation ends close the data file
+            .add_system_set(SystemSet::on_update(GrenadeState::Fragment).with_system(close_data_writer_on_end.after(sim_end::check_end_conditions)))
+
+            // When the fragment part of the simulation is left early close the data file
+            .add_system_set(SystemSet::on_exit(GrenadeState::Fragment).with_system(close_data_writer));
+    }
+}
+
+// Why a fragment was logged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleEvent {
+    // Fragment as it left the grenade
+    Launch,
+    // Regular sample at the logging rate
+    Sample,
+    // Fragment started touching a surface or another fragment
+    Impact,
+    // Fragment dropped below rest_velocity
+    Rest,
+    // Fragment left the tracking radius and is no longer simulated
+    Escaped,
+}
+
+impl fmt::Display for SampleEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SampleEvent::Launch => write!(f, "launch"),
+            SampleEvent::Sample => write!(f, "sample"),
+            SampleEvent::Impact => write!(f, "impact"),
+            SampleEvent::Rest => write!(f, "rest"),
+            SampleEvent::Escaped => write!(f, "escaped"),
+        }
+    }
+}
+
+// Snapshot of one fragment
+pub struct FragmentSample {
+    pub fragment_id: u32,
+    pub event: SampleEvent,
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub mass: f32,
+    pub size: f32,
+    // What an impact was against, a surface name or "fragment"
+    pub hit: Option<String>,
+}
+
+// Grenade position and motion at the moment it exploded
+#[derive(Clone, Copy, Serialize)]
+pub struct Detonation {
+    pub position: Vec3,
+    pub rotation: Quat,
+    pub velocity: Vec3,
+    pub angular_velocity: Vec3,
+}
+
+impl From<&GrenadeData> for Detonation {
+    fn from(grenade_data: &GrenadeData) -> Self {
+        Detonation {
+            position: grenade_data.last_location.translation,
+            rotation: grenade_data.last_location.rotation,
+            velocity: grenade_data.last_vel.linvel,
+            angular_velocity: grenade_data.last_vel.angvel,
+        }
+    }
+}
+
+// Every fragment at one point in simulated time
+pub struct StepRecord {
+    pub sim_time: f32,
+    // Real time the frame took in ms, the first column of the legacy layout
+    pub frame_millis: u128,
+    pub samples: Vec<FragmentSample>,
+}
+
+// File format of the fragment data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    Csv,
+    Parquet,
+    Jsonl,
+}
+
+impl OutputFormat {
+    // Format set in SimSettings, or picked from the extension of csv_location
+    pub fn from_settings(sim_settings: &SimSettings) -> Self {
+        sim_settings.output_format.unwrap_or_else(|| OutputFormat::from_path(&sim_settings.csv_location))
+    }
+
+    // Pick the format from the file extension, anything unknown is written as csv
+    pub fn from_path(path: &str) -> Self {
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("parquet") || ext.eq_ignore_ascii_case("pq") => OutputFormat::Parquet,
+            Some(ext) if ext.eq_ignore_ascii_case("jsonl") => OutputFormat::Jsonl,
+            _ => OutputFormat::Csv,
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "csv" => Ok(OutputFormat::Csv),
+            "parquet" => Ok(OutputFormat::Parquet),
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            other => Err(format!("Unknown output_format {}, expected csv, parquet or jsonl.", other)),
+        }
+    }
+}
+
+// Destination for fragment data, implemented once for every output format
+pub trait TrajectoryWriter: Send + Sync {
+    // Record the settings and seed of the run, formats without a place for them skip it
+    fn start(&mut self, _sim_settings: &SimSettings, _seed: u64) -> OutputResult<()> {
+        Ok(())
+    }
+
+    // Record where the grenade was and how it moved when it exploded
+    fn detonation(&mut self, _grenade_data: &GrenadeData) -> OutputResult<()> {
+        Ok(())
+    }
+
+    // Write every fragment of one step
+    fn write(&mut self, step: &StepRecord) -> OutputResult<()>;
+
+    // Record why the simulation stopped
+    fn end(&mut self, _ended: &SimulationEnded) -> OutputResult<()> {
+        Ok(())
+    }
+
+    // Flush everything still buffered and close the file
+    fn finish(self: Box<Self>) -> OutputResult<()>;
+}
+
+// Open the data file before a run starts, with the message to show when it can not be
+pub fn prepare_output(sim_settings: &SimSettings) -> Result<OutputFile, String> {
+    OutputFile::open(sim_settings).map_err(|err| format!("Could not output data to {}: {}", sim_settings.csv_location, err))
+}
+
+// Write to the opened data file in the format from SimSettings
+fn create_output(sim_settings: &SimSettings, output_file: OutputFile, seed: u64) -> OutputResult<Box<dyn TrajectoryWriter>> {
+    let OutputFile { file, has_data, .. } = output_file;
+    Ok(match OutputFormat::from_settings(sim_settings) {
+        OutputFormat::Csv => Box::new(CsvOutput::create(file, sim_settings.output_layout, seed, has_data)?),
+        OutputFormat::Parquet => Box::new(ParquetOutput::create(file, seed)?),
+        OutputFormat::Jsonl => Box::new(JsonlOutput::create(file)),
+    })
+}
+
+// Data file kept open for the whole fragment simulation
+pub struct DataWriter {
+    path: PathBuf,
+    sink: Sink,
+}
+
+// Where the rows of the data file are written
+enum Sink {
+    // Rows are written on the simulation thread
+    Direct(Box<dyn TrajectoryWriter>),
+    // Rows are sent to a background thread that does the writing
+    Background {
+        sender: Sender<StepRecord>,
+        worker: JoinHandle<OutputResult<Box<dyn TrajectoryWriter>>>,
+    },
+}
+
+impl DataWriter {
+    // Start writing the opened data file and record how the run started
+    pub fn open(sim_settings: &SimSettings, output_file: OutputFile, seed: u64, grenade_data: &GrenadeData) -> OutputResult<Self> {
+        let path = output_file.path.clone();
+        let mut output = create_output(sim_settings, output_file, seed)?;
+        output.start(sim_settings, seed)?;
+        output.detonation(grenade_data)?;
+
+        if !sim_settings.writer_thread {
+            return Ok(DataWriter { path, sink: Sink::Direct(output) });
+        }
+
+        // Hand the file to a thread that writes everything it receives, then hands it back
+        let (sender, receiver) = mpsc::channel::<StepRecord>();
+        let worker = thread::spawn(move || {
+            for step in receiver {
+                output.write(&step)?;
+            }
+            Ok(output)
+        });
+
+        Ok(DataWriter { path, sink: Sink::Background { sender, worker } })
+    }
+
+    // Path the data is written to
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Write one step of fragment data
+    pub fn write(&mut self, step: StepRecord) -> OutputResult<()> {
+        match &mut self.sink {
+            Sink::Direct(output) => output.write(&step),
+
+            // The thread only hangs up when it failed, the error is returned by close
+            Sink::Background { sender, .. } => sender.send(step).map_err(|_| "writer thread stopped".into()),
+        }
+    }
+
+    // Record how the simulation ended if it did, then flush and close the data file
+    pub fn close(self, ended: Option<&SimulationEnded>) -> OutputResult<()> {
+        let mut output = match self.sink {
+            Sink::Direct(output) => output,
+            Sink::Background { sender, worker } => {
+                // Hanging up lets the thread finish the remaining rows
+                drop(sender);
+                worker.join().map_err(|_| "writer thread panicked")??
+            }
+        };
+
+        if let Some(ended) = ended {
+            output.end(ended)?;
+        }
+        output.finish()
+    }
+}
+
+// Report a failed write and fail a headless run
+fn report_error(err: Box<dyn Error + Send + Sync>, path: &Path, exit_code: Option<&ExitCode>) {
+    println!("Could not output data to {}: {}", path.display(), err);
+
+    if let Some(exit_code) = exit_code {
+        exit_code.fail();
+    }
+}
+
+// Start writing the data file opened before the run when the fragments are created
+pub fn open_data_writer(mut commands: Commands, exit_code: Option<Res<ExitCode>>) {
+    commands.add(OpenDataWriter { exit_code: exit_code.as_deref().cloned() });
+}
+
+// Command that turns the opened data file into the writer, it needs ownership of the file so it takes it out of the world
+struct OpenDataWriter {
+    exit_code: Option<ExitCode>,
+}
+
+impl Command for OpenDataWriter {
+    fn write(self, world: &mut World) {
+        // Every way of starting a run opens the file first and does not start without it
+        let output_file = match world.remove_resource::<OutputFile>() {
+            Some(output_file) => output_file,
+            None => return,
+        };
+
+        let path = output_file.path.clone();
+        let has_data = output_file.has_data;
+        let sim_settings = world.resource::<SimSettings>();
+        let seed = world.resource::<SimRng>().seed;
+        let grenade_data = world.resource::<GrenadeData>();
+        let writer = match DataWriter::open(sim_settings, output_file, seed, grenade_data) {
+            Ok(writer) => writer,
+            Err(err) => {
+                report_error(err, &path, self.exit_code.as_ref());
+                return;
+            }
+        };
+
+        // Keep what is known at the start of the run for the metadata file
+        let metadata = RunMetadata::new(sim_settings, &path, seed, grenade_data, has_data);
+        world.insert_resource(writer);
+        world.insert_resource(metadata);
+        world.insert_resource(DataFile(path));
+    }
+}
+
+// What has already been logged during the current simulation
+#[derive(Default)]
+pub struct LogState {
+    // Index of the last log_interval that got a sample
+    last_interval: Option<u64>,
+    // Fragments that have been logged as resting
+    resting: HashSet<u32>,
+}
+
+impl LogState {
+    // Check if this step falls on the sampling rate from SimSettings
+    fn sample_due(&mut self, clock: &SimClock, sim_settings: &SimSettings) -> bool {
+        if sim_settings.log_interval > 0.0 {
+            // Log the first step of every new interval
+            let interval = (clock.elapsed / sim_settings.log_interval).floor() as u64;
+            if self.last_interval == Some(interval) {
+                return false;
+            }
+            self.last_interval = Some(interval);
+            true
+        } else {
+            clock.steps.is_multiple_of(sim_settings.log_every_n_steps.max(1))
+        }
+    }
+}
+
+// Start every simulation without anything logged
+fn reset_log_state(mut commands: Commands) {
+    commands.insert_resource(LogState::default());
+}
+
+type FragmentData<'a> = (&'a FragmentId, &'a Transform, &'a Velocity, &'a FragmentProperties);
+
+// Snapshot a fragment from its components
+fn sample((id, pos, vel, properties): FragmentData, event: SampleEvent) -> FragmentSample {
+    FragmentSample {
+        fragment_id: id.0,
+        event,
+        position: pos.translation,
+        velocity: vel.linvel,
+        mass: properties.mass,
+        size: properties.size,
+        hit: None,
+    }
+}
+
+// Export data during simulation
+pub fn write_fragment_data(
+    mut commands: Commands,
+    fragments: Query<FragmentData, With<Fragment>>,
+    surface_names: Query<&SurfaceName>,
+    mut collisions: EventReader<CollisionEvent>,
+    mut escaped: EventReader<FragmentEscaped>,
+    clock: Res<SimClock>,
+    time: Res<Time>,
+    sim_settings: Res<SimSettings>,
+    writer: Option<ResMut<DataWriter>>,
+    exit_code: Option<Res<ExitCode>>,
+    mut log_state: ResMut<LogState>
+) {
+    // Nothing to do if the file could not be opened
+    let mut writer = match writer {
+        Some(writer) => writer,
+        None => return,
+    };
+
+    let mut samples = vec![];
+
+    // The first update shows every fragment as it was launched
+    let sample_due = log_state.sample_due(&clock, &sim_settings);
+    if clock.steps == 0 {
+        samples.extend(fragments.iter().map(|fragment| sample(fragment, SampleEvent::Launch)));
+    } else if sample_due && !sim_settings.log_events_only {
+        samples.extend(fragments.iter().map(|fragment| sample(fragment, SampleEvent::Sample)));
+    }
+
+    // Log fragments that started touching something, along with what they hit
+    for collision in collisions.iter() {
+        if let CollisionEvent::Started(first, second, _) = collision {
+            for (entity, other) in [(first, second), (second, first)] {
+                if let Ok(fragment) = fragments.get(*entity) {
+                    let hit = if fragments.get(*other).is_ok() {
+                        Some("fragment".to_string())
+                    } else {
+                        surface_names.get(*other).ok().map(|name| name.0.clone())
+                    };
+                    samples.push(FragmentSample { hit, ..sample(fragment, SampleEvent::Impact) });
+                }
+            }
+        }
+    }
+
+    // Log fragments when they come to rest, and again if they are knocked loose and settle
+    if sim_settings.rest_velocity > 0.0 {
+        for fragment in fragments.iter() {
+            let id = fragment.0.0;
+            if fragment.2.linvel.length() >= sim_settings.rest_velocity {
+                log_state.resting.remove(&id);
+            } else if log_state.resting.insert(id) {
+                samples.push(sample(fragment, SampleEvent::Rest));
+            }
+        }
+    }
+
+    // Log where fragments were when they left the tracking radius, they are gone after this update
+    for FragmentEscaped(entity) in escaped.iter() {
+        if let Ok(fragment) = fragments.get(*entity) {
+            samples.push(sample(fragment, SampleEvent::Escaped));
+        }
+    }
+
+    if samples.is_empty() {
+        return;
+    }
+
+    // Order fragments by ID so they are written in the same order every update
+    samples.sort_by_key(|sample| sample.fragment_id);
+
+    // Stop writing after the first failure
+    if let Err(err) = writer.write(StepRecord { sim_time: clock.elapsed, frame_millis: time.delta().as_millis(), samples }) {
+        commands.add(StopDataWriter { err, exit_code: exit_code.as_deref().cloned() });
+    }
+}
+
+// Close the data file once the simulation has ended
+fn close_data_writer_on_end(
+    mut ended: EventReader<SimulationEnded>,
+    mut commands: Commands,
+    exit_code: Option<Res<ExitCode>>
+) {
+    if let Some(ended) = ended.iter().next() {
+        queue_close(&mut commands, Some(*ended), exit_code.as_deref());
+    }
+}
+
+// Close the data file when leaving the fragment simulation
+pub fn close_data_writer(mut commands: Commands, exit_code: Option<Res<ExitCode>>) {
+    queue_close(&mut commands, None, exit_code.as_deref());
+}
+
+// Closing needs ownership of the writer, so take it out of the world in a command
+fn queue_close(commands: &mut Commands, ended: Option<SimulationEnded>, exit_code: Option<&ExitCode>) {
+    commands.add(CloseDataWriter {
+        ended,
+        exit_code: exit_code.cloned(),
+    });
+}
+
+// Command that closes the data file after a failed write and reports why it failed
+struct StopDataWriter {
+    err: Box<dyn Error + Send + Sync>,
+    exit_code: Option<ExitCode>,
+}
+
+impl Command for StopDataWriter {
+    fn write(self, world: &mut World) {
+        if let Some(writer) = world.remove_resource::<DataWriter>() {
+            let path = writer.path().to_path_buf();
+
+            // A writer thread only hangs up when it failed, closing joins it and returns the error that stopped it
+            let err = writer.close(None).err().unwrap_or(self.err);
+            report_error(err, &path, self.exit_code.as_ref());
+        }
+    }
+}
+
+// Command that flushes and closes the data file if it is still open and writes the run metadata
+struct CloseDataWriter {
+    ended: Option<SimulationEnded>,
+    exit_code: Option<ExitCode>,
+}
+
+impl Command for CloseDataWriter {
+    fn write(self, world: &mut World) {
+        if let Some(writer) = world.remove_resource::<DataWriter>() {
+            let path = writer.path().to_path_buf();
+            if let Err(err) = writer.close(self.ended.as_ref()) {
+                report_error(err, &path, self.exit_code.as_ref());
+            }
+        }
+
+        if let Some(mut metadata) = world.remove_resource::<RunMetadata>() {
+            let sim_settings = world.resource::<SimSettings>();
+            let (rest_velocity, arena) = (sim_settings.rest_velocity, sim_settings.arena.clone());
+            metadata.measure_fragments(world, rest_velocity, &arena);
+
+            let sim_settings = world.resource::<SimSettings>();
+            let run_timer = world.get_resource::<RunTimer>();
+            let path = metadata::metadata_path(metadata.data_file());
+            if let Err(err) = metadata.write(sim_settings, self.ended.as_ref(), run_timer) {
+                report_error(err, &path, self.exit_code.as_ref());
+            }
+        }
+    }
+}
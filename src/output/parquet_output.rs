@@ -0,0 +1,135 @@
+use parquet::{
+    basic::Compression,
+    data_type::{ByteArray, ByteArrayType, DataType, FloatType, Int32Type, Int64Type},
+    file::{properties::WriterProperties, writer::{SerializedFileWriter, SerializedRowGroupWriter}},
+    schema::parser::parse_message_type,
+};
+use std::{fs::File, mem, sync::Arc};
+
+use super::{OutputResult, StepRecord, TrajectoryWriter};
+
+// Columns of the file, the same as the long csv layout
+const SCHEMA: &str = "
+    message fragment_data {
+        REQUIRED FLOAT sim_time;
+        REQUIRED INT32 fragment_id (INTEGER(32, false));
+        REQUIRED BYTE_ARRAY event (UTF8);
+        REQUIRED FLOAT x;
+        REQUIRED FLOAT y;
+        REQUIRED FLOAT z;
+        REQUIRED FLOAT vx;
+        REQUIRED FLOAT vy;
+        REQUIRED FLOAT vz;
+        REQUIRED FLOAT speed;
+        REQUIRED FLOAT mass;
+        REQUIRED FLOAT size;
+        REQUIRED INT64 seed (INTEGER(64, false));
+        REQUIRED BYTE_ARRAY hit (UTF8);
+    }
+";
+
+// Rows kept in memory before they are compressed into a row group
+const ROW_GROUP_ROWS: usize = 100_000;
+
+// Rows waiting for the next row group, one vector per column
+#[derive(Default)]
+struct Columns {
+    sim_time: Vec<f32>,
+    fragment_id: Vec<i32>,
+    event: Vec<ByteArray>,
+    x: Vec<f32>,
+    y: Vec<f32>,
+    z: Vec<f32>,
+    vx: Vec<f32>,
+    vy: Vec<f32>,
+    vz: Vec<f32>,
+    speed: Vec<f32>,
+    mass: Vec<f32>,
+    size: Vec<f32>,
+    hit: Vec<ByteArray>,
+}
+
+// Snappy compressed parquet file kept open for the whole simulation
+pub struct ParquetOutput {
+    writer: SerializedFileWriter<File>,
+    columns: Columns,
+    seed: u64,
+}
+
+impl ParquetOutput {
+    // Start the file, the schema is written when it is closed
+    pub fn create(file: File, seed: u64) -> OutputResult<Self> {
+        let schema = Arc::new(parse_message_type(SCHEMA)?);
+        let properties = Arc::new(WriterProperties::builder().set_compression(Compression::SNAPPY).build());
+        let writer = SerializedFileWriter::new(file, schema, properties)?;
+
+        Ok(ParquetOutput { writer, columns: Columns::default(), seed })
+    }
+
+    // Compress the buffered rows into a row group
+    fn flush_row_group(&mut self) -> OutputResult<()> {
+        if self.columns.sim_time.is_empty() {
+            return Ok(());
+        }
+
+        let columns = mem::take(&mut self.columns);
+        let seed = vec![self.seed as i64; columns.sim_time.len()];
+
+        // Columns have to be written in the order of the schema
+        let mut row_group = self.writer.next_row_group()?;
+        write_column::<FloatType>(&mut row_group, &columns.sim_time)?;
+        write_column::<Int32Type>(&mut row_group, &columns.fragment_id)?;
+        write_column::<ByteArrayType>(&mut row_group, &columns.event)?;
+        for values in [&columns.x, &columns.y, &columns.z, &columns.vx, &columns.vy, &columns.vz, &columns.speed, &columns.mass, &columns.size] {
+            write_column::<FloatType>(&mut row_group, values)?;
+        }
+        write_column::<Int64Type>(&mut row_group, &seed)?;
+        write_column::<ByteArrayType>(&mut row_group, &columns.hit)?;
+        row_group.close()?;
+
+        Ok(())
+    }
+}
+
+impl TrajectoryWriter for ParquetOutput {
+    // Buffer every fragment of one step
+    fn write(&mut self, step: &StepRecord) -> OutputResult<()> {
+        let columns = &mut self.columns;
+        for sample in &step.samples {
+            columns.sim_time.push(step.sim_time);
+            columns.fragment_id.push(sample.fragment_id as i32);
+            columns.event.push(ByteArray::from(sample.event.to_string().as_str()));
+            columns.x.push(sample.position.x);
+            columns.y.push(sample.position.y);
+            columns.z.push(sample.position.z);
+            columns.vx.push(sample.velocity.x);
+            columns.vy.push(sample.velocity.y);
+            columns.vz.push(sample.velocity.z);
+            columns.speed.push(sample.velocity.length());
+            columns.mass.push(sample.mass);
+            columns.size.push(sample.size);
+            columns.hit.push(ByteArray::from(sample.hit.as_deref().unwrap_or_default()));
+        }
+
+        if self.columns.sim_time.len() >= ROW_GROUP_ROWS {
+            self.flush_row_group()?;
+        }
+
+        Ok(())
+    }
+
+    // Write the last row group and the footer
+    fn finish(mut self: Box<Self>) -> OutputResult<()> {
+        self.flush_row_group()?;
+        self.writer.close()?;
+        Ok(())
+    }
+}
+
+// Write the next column of a row group
+fn write_column<T: DataType>(row_group: &mut SerializedRowGroupWriter<File>, values: &[T::T]) -> OutputResult<()> {
+    let mut column = row_group.next_column()?.ok_or("more columns than the parquet schema")?;
+    column.typed::<T>().write_batch(values, None, None)?;
+    column.close()?;
+    Ok(())
+}
@@ -0,0 +1,295 @@
+use bevy::math::Vec3;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::config::parse_setting;
+
+use super::{obstacle::ObstacleSettings, terrain::TerrainSettings};
+
+// How far past the floor, walls or ceiling a fragment can be before it counts as outside
+const ARENA_TOLERANCE: f32 = 0.5;
+
+// Distance from the middle to the edge of the open field ground when fragments are tracked forever
+const OPEN_FIELD_HALF_SIZE: f32 = 1000.0;
+
+// Kind of place the grenade is thrown in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArenaPreset {
+    // Floor with walls and an optional ceiling, sized by the arena settings
+    Room,
+    // Ground reaching past the tracking radius with nothing around it
+    OpenField,
+    // Nothing but the obstacles
+    None,
+}
+
+impl FromStr for ArenaPreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "room" => Ok(ArenaPreset::Room),
+            "open_field" => Ok(ArenaPreset::OpenField),
+            "none" => Ok(ArenaPreset::None),
+            other => Err(format!("Unknown arena preset {}, expected room, open_field or none.", other)),
+        }
+    }
+}
+
+// Place the grenade is thrown in, measured on the inside with the floor at y = 0 and centered on x = z = 0
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArenaSettings {
+    #[serde(deserialize_with = "parse_setting")]
+    pub preset: ArenaPreset,
+    // Fragments further than this from the detonation are removed and recorded as escaped, 0 tracks them forever
+    pub tracking_radius: f32,
+    // Scene files placed in the arena with every preset
+    pub obstacles: Vec<ObstacleSettings>,
+    // Height map laid over the floor of the room or the ground of the open field
+    pub terrain: TerrainSettings,
+    // Everything below only applies to the room
+    // Size along x
+    pub width: f32,
+    // Size along z
+    pub depth: f32,
+    pub wall_height: f32,
+    pub wall_thickness: f32,
+    pub floor_thickness: f32,
+    pub walls: WallSettings,
+    // Close the room at the top of the walls
+    pub ceiling: bool,
+}
+
+// Which walls exist, north and south are at +z and -z, east and west at +x and -x
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WallSettings {
+    pub north: bool,
+    pub south: bool,
+    pub east: bool,
+    pub west: bool,
+}
+
+// A solid box of the arena, the mesh and the collider are both built from it
+pub struct Surface {
+    // Written in the hit column of impacts with the surface
+    pub name: &'static str,
+    pub center: Vec3,
+    pub half_extents: Vec3,
+}
+
+impl Default for ArenaSettings {
+    fn default() -> Self {
+        ArenaSettings {
+            preset: ArenaPreset::Room,
+            tracking_radius: 500.0,
+            obstacles: vec![],
+            terrain: TerrainSettings::default(),
+            width: 100.0,
+            depth: 100.0,
+            wall_height: 75.0,
+            wall_thickness: 1.0,
+            floor_thickness: 10.0,
+            walls: WallSettings::default(),
+            ceiling: false,
+        }
+    }
+}
+
+impl Default for WallSettings {
+    fn default() -> Self {
+        WallSettings { north: true, south: true, east: true, west: true }
+    }
+}
+
+impl ArenaSettings {
+    // Half of the inside size along x and z
+    pub fn half_size(&self) -> (f32, f32) {
+        (self.width / 2.0, self.depth / 2.0)
+    }
+
+    // Keep a point inside the walls and above the ground, which is at ground_height under the point
+    pub fn clamp(&self, position: Vec3, ground_height: f32) -> Vec3 {
+        match self.preset {
+            ArenaPreset::Room => {}
+            ArenaPreset::OpenField => return Vec3::new(position.x, position.y.max(ground_height), position.z),
+            ArenaPreset::None => return position,
+        }
+
+        let (half_width, half_depth) = self.half_size();
+        Vec3::new(
+            position.x.clamp(-half_width, half_width),
+            position.y.max(ground_height),
+            position.z.clamp(-half_depth, half_depth),
+        )
+    }
+
+    // Whether a point is below the ground, beyond the walls or above the ceiling
+    // Fragments only get there by tunneling, or by flying over a wall or through a missing one
+    pub fn is_outside(&self, position: Vec3, ground_height: f32) -> bool {
+        match self.preset {
+            ArenaPreset::Room => {}
+            ArenaPreset::OpenField => return position.y < ground_height - ARENA_TOLERANCE,
+            ArenaPreset::None => return false,
+        }
+
+        let (half_width, half_depth) = self.half_size();
+        position.y < ground_height - ARENA_TOLERANCE
+            || (self.ceiling && position.y > self.wall_height + ARENA_TOLERANCE)
+            || position.x.abs() > half_width + ARENA_TOLERANCE
+            || position.z.abs() > half_depth + ARENA_TOLERANCE
+    }
+
+    // Whether a fragment is far enough from the detonation to stop tracking it
+    pub fn has_escaped(&self, position: Vec3, detonation: Vec3) -> bool {
+        self.tracking_radius > 0.0 && position.distance(detonation) > self.tracking_radius
+    }
+
+    // Floor, walls and ceiling, the floor and ceiling reach under and over the walls
+    // The open field only has ground, reaching out to the tracking radius around the detonation once it is moved there
+    // A terrain replaces the floor or ground, terrain_bottom is its lowest point and the walls reach down to it
+    pub fn surfaces(&self, terrain_bottom: Option<f32>) -> Vec<Surface> {
+        let half_floor = self.floor_thickness / 2.0;
+        match self.preset {
+            ArenaPreset::Room => {}
+            ArenaPreset::None => return vec![],
+            ArenaPreset::OpenField if terrain_bottom.is_some() => return vec![],
+            ArenaPreset::OpenField => {
+                let half_size = if self.tracking_radius > 0.0 { self.tracking_radius } else { OPEN_FIELD_HALF_SIZE };
+                return vec![Surface {
+                    name: "ground",
+                    center: Vec3::new(0.0, -half_floor, 0.0),
+                    half_extents: Vec3::new(half_size, half_floor, half_size),
+                }];
+            }
+        }
+
+        let (half_width, half_depth) = self.half_size();
+        let thickness = self.wall_thickness;
+        let bottom = terrain_bottom.map_or(0.0, |bottom| bottom.min(0.0));
+        let half_height = (self.wall_height - bottom) / 2.0;
+        let middle_height = (self.wall_height + bottom) / 2.0;
+
+        // Floor
+        let mut surfaces = vec![];
+        if terrain_bottom.is_none() {
+            surfaces.push(Surface {
+                name: "floor",
+                center: Vec3::new(0.0, -half_floor, 0.0),
+                half_extents: Vec3::new(half_width + thickness, half_floor, half_depth + thickness),
+            });
+        }
+
+        // North and south walls span the corners
+        let north_south = Vec3::new(half_width + thickness, half_height, thickness / 2.0);
+        if self.walls.north {
+            surfaces.push(Surface { name: "north_wall", center: Vec3::new(0.0, middle_height, half_depth + thickness / 2.0), half_extents: north_south });
+        }
+        if self.walls.south {
+            surfaces.push(Surface { name: "south_wall", center: Vec3::new(0.0, middle_height, -half_depth - thickness / 2.0), half_extents: north_south });
+        }
+
+        // East and west walls fit between them
+        let east_west = Vec3::new(thickness / 2.0, half_height, half_depth);
+        if self.walls.east {
+            surfaces.push(Surface { name: "east_wall", center: Vec3::new(half_width + thickness / 2.0, middle_height, 0.0), half_extents: east_west });
+        }
+        if self.walls.west {
+            surfaces.push(Surface { name: "west_wall", center: Vec3::new(-half_width - thickness / 2.0, middle_height, 0.0), half_extents: east_west });
+        }
+
+        if self.ceiling {
+            surfaces.push(Surface {
+                name: "ceiling",
+                center: Vec3::new(0.0, self.wall_height + thickness / 2.0, 0.0),
+                half_extents: Vec3::new(half_width + thickness, thickness / 2.0, half_depth + thickness),
+            });
+        }
+
+        surfaces
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn room() -> ArenaSettings {
+        ArenaSettings { width: 40.0, depth: 30.0, wall_height: 10.0, wall_thickness: 1.0, floor_thickness: 2.0, ceiling: true, ..ArenaSettings::default() }
+    }
+
+    // Lowest and highest corner of a surface
+    fn bounds(surfaces: &[Surface], name: &str) -> (Vec3, Vec3) {
+        let surface = surfaces.iter().find(|surface| surface.name == name).unwrap_or_else(|| panic!("no {}", name));
+        (surface.center - surface.half_extents, surface.center + surface.half_extents)
+    }
+
+    #[test]
+    fn room_walls_meet_the_floor_and_ceiling() {
+        let surfaces = room().surfaces(None);
+        let (floor_min, floor_max) = bounds(&surfaces, "floor");
+        let (ceiling_min, ceiling_max) = bounds(&surfaces, "ceiling");
+        assert_eq!(floor_max.y, 0.0);
+        assert_eq!(ceiling_min.y, 10.0);
+
+        for wall in ["north_wall", "south_wall", "east_wall", "west_wall"] {
+            let (min, max) = bounds(&surfaces, wall);
+            assert_eq!((min.y, max.y), (0.0, 10.0), "{} does not reach from the floor to the ceiling", wall);
+
+            // The floor and ceiling reach under and over the whole wall
+            for (cover_min, cover_max) in [(floor_min, floor_max), (ceiling_min, ceiling_max)] {
+                assert!(cover_min.x <= min.x && cover_max.x >= max.x && cover_min.z <= min.z && cover_max.z >= max.z, "{} sticks out", wall);
+            }
+        }
+
+        // The inside faces of the walls are at the size of the room and the corners are closed
+        let (north_min, _) = bounds(&surfaces, "north_wall");
+        let (_, south_max) = bounds(&surfaces, "south_wall");
+        let (east_min, east_max) = bounds(&surfaces, "east_wall");
+        let (west_min, west_max) = bounds(&surfaces, "west_wall");
+        assert_eq!((north_min.z, south_max.z, east_min.x, west_max.x), (15.0, -15.0, 20.0, -20.0));
+        assert_eq!((east_min.z, east_max.z, west_min.z, west_max.z), (-15.0, 15.0, -15.0, 15.0));
+        assert_eq!((north_min.x, south_max.x), (-21.0, 21.0));
+    }
+
+    #[test]
+    fn walls_reach_down_to_the_terrain() {
+        let surfaces = room().surfaces(Some(-3.0));
+        assert!(surfaces.iter().all(|surface| surface.name != "floor"));
+        for wall in ["north_wall", "south_wall", "east_wall", "west_wall"] {
+            assert_eq!(bounds(&surfaces, wall).0.y, -3.0);
+            assert_eq!(bounds(&surfaces, wall).1.y, 10.0);
+        }
+
+        // Terrain above 0 still leaves the walls standing on 0
+        let surfaces = room().surfaces(Some(2.0));
+        assert_eq!(bounds(&surfaces, "east_wall").0.y, 0.0);
+    }
+
+    #[test]
+    fn open_field_ground() {
+        let open_field = ArenaSettings { preset: ArenaPreset::OpenField, tracking_radius: 200.0, floor_thickness: 2.0, ..ArenaSettings::default() };
+        let surfaces = open_field.surfaces(None);
+        assert_eq!(surfaces.len(), 1);
+        let (min, max) = bounds(&surfaces, "ground");
+        assert_eq!((min.x, max.x, max.y, min.z, max.z), (-200.0, 200.0, 0.0, -200.0, 200.0));
+
+        // A terrain replaces the ground
+        assert!(open_field.surfaces(Some(-1.0)).is_empty());
+    }
+
+    #[test]
+    fn clamp_keeps_points_inside_the_walls() {
+        let room = room();
+        assert_eq!(room.clamp(Vec3::new(80.0, -5.0, -70.0), 0.0), Vec3::new(20.0, 0.0, -15.0));
+        assert_eq!(room.clamp(Vec3::new(-25.0, 4.0, 16.0), 1.0), Vec3::new(-20.0, 4.0, 15.0));
+        assert_eq!(room.clamp(Vec3::new(3.0, 2.0, -4.0), 0.0), Vec3::new(3.0, 2.0, -4.0));
+        assert!(!room.is_outside(room.clamp(Vec3::new(1000.0, -1000.0, 1000.0), 0.0), 0.0));
+
+        // The open field only keeps points above the ground
+        let open_field = ArenaSettings { preset: ArenaPreset::OpenField, ..room };
+        assert_eq!(open_field.clamp(Vec3::new(80.0, -5.0, -70.0), 2.0), Vec3::new(80.0, 2.0, -70.0));
+    }
+}
@@ -0,0 +1,150 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use std::{error::Error, fs};
+
+use super::{fragment::{Fragment, FragmentProperties}, sim_settings::SimSettings};
+
+// How the drag coefficient is found for a fragment
+pub enum DragModel {
+    // No air resistance
+    Off,
+    // Same coefficient at every speed
+    Constant(f32),
+    // Coefficient interpolated from (mach, cd) pairs sorted by mach
+    Table(Vec<(f32, f32)>),
+}
+
+impl DragModel {
+    // Drag coefficient at the given mach number
+    fn coefficient(&self, mach: f32) -> f32 {
+        match self {
+            DragModel::Off => 0.0,
+            DragModel::Constant(cd) => *cd,
+            DragModel::Table(table) => interpolate(table, mach),
+        }
+    }
+}
+
+// Build the drag model from SimSettings when the fragments are created
+pub fn setup_drag(mut commands: Commands, sim_settings: Res<SimSettings>) {
+    let model = if !sim_settings.drag {
+        DragModel::Off
+    } else if sim_settings.drag_table.is_empty() {
+        DragModel::Constant(sim_settings.drag_coefficient)
+    } else {
+        match read_drag_table(&sim_settings.drag_table) {
+            Ok(table) => DragModel::Table(table),
+            Err(err) => {
+                println!("Could not read drag table {}: {}. Using drag_coefficient.", sim_settings.drag_table, err);
+                DragModel::Constant(sim_settings.drag_coefficient)
+            }
+        }
+    };
+
+    commands.insert_resource(model);
+}
+
+// Set the drag force on every fragment from its current velocity
+pub fn apply_drag(
+    mut fragments: Query<(&Velocity, &FragmentProperties, &mut ExternalForce), With<Fragment>>,
+    model: Res<DragModel>,
+    sim_settings: Res<SimSettings>,
+    time: Res<Time>
+) {
+    if let DragModel::Off = *model {
+        return;
+    }
+
+    let dt = sim_settings.step_seconds(&time);
+    if dt <= 0.0 {
+        return;
+    }
+
+    for (vel, properties, mut external_force) in fragments.iter_mut() {
+        let speed = vel.linvel.length();
+        let cd = model.coefficient(speed / sim_settings.speed_of_sound);
+
+        // Average presented area of a tumbling cube is a quarter of its surface area
+        let area = 1.5 * properties.size * properties.size;
+
+        // Quadratic drag gives dv/dt = -k |v| v
+        let k = 0.5 * sim_settings.air_density * cd * area / properties.mass;
+
+        // Solve a whole step exactly so light fragments can not overshoot and reverse
+        let next_vel = vel.linvel / (1.0 + k * speed * dt);
+        external_force.force = properties.mass * (next_vel - vel.linvel) / dt;
+    }
+}
+
+// Read a csv file of "mach,cd" rows
+fn read_drag_table(path: &str) -> Result<Vec<(f32, f32)>, Box<dyn Error>> {
+    let contents = fs::read_to_string(path)?;
+
+    let mut table = vec![];
+    for line in contents.lines() {
+        let cells: Vec<&str> = line.split(',').map(|cell| cell.trim()).collect();
+
+        // Skip a header or any other row that is not two numbers
+        if let [mach, cd] = cells[..] {
+            if let (Ok(mach), Ok(cd)) = (mach.parse::<f32>(), cd.parse::<f32>()) {
+                table.push((mach, cd));
+            }
+        }
+    }
+
+    if table.is_empty() {
+        return Err("no mach,cd rows".into());
+    }
+
+    table.sort_by(|a, b| a.0.total_cmp(&b.0));
+    Ok(table)
+}
+
+// Linearly interpolate the table, holding the end values outside of it
+fn interpolate(table: &[(f32, f32)], mach: f32) -> f32 {
+    let first = table[0];
+    let last = table[table.len() - 1];
+
+    if mach <= first.0 {
+        return first.1;
+    }
+    if mach >= last.0 {
+        return last.1;
+    }
+
+    for pair in table.windows(2) {
+        let ((m0, cd0), (m1, cd1)) = (pair[0], pair[1]);
+        if mach <= m1 {
+            return cd0 + (cd1 - cd0) * (mach - m0) / (m1 - m0);
+        }
+    }
+
+    last.1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TABLE: [(f32, f32); 3] = [(0.5, 0.8), (1.0, 1.2), (2.0, 1.0)];
+
+    #[test]
+    fn interpolate_holds_the_end_values() {
+        assert_eq!(interpolate(&TABLE, 0.0), 0.8);
+        assert_eq!(interpolate(&TABLE, 0.5), 0.8);
+        assert_eq!(interpolate(&TABLE, 2.0), 1.0);
+        assert_eq!(interpolate(&TABLE, 5.0), 1.0);
+    }
+
+    #[test]
+    fn interpolate_hits_the_sample_points() {
+        assert_eq!(interpolate(&TABLE, 1.0), 1.2);
+        assert_eq!(interpolate(&[(1.0, 0.3)], 1.0), 0.3);
+    }
+
+    #[test]
+    fn interpolate_between_sample_points() {
+        assert!((interpolate(&TABLE, 0.75) - 1.0).abs() < 1.0e-6);
+        assert!((interpolate(&TABLE, 1.5) - 1.1).abs() < 1.0e-6);
+    }
+}
@@ -1,81 +1,92 @@
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
-use csv::Writer;
-use rand::prelude::*;
-use std::{error::Error, fs};
 
-use super::{sim_settings::SimSettings, grenade::GrenadeData};
+use super::{sim_settings::SimSettings, grenade::GrenadeData, terrain::{ground_height, Terrain}, sim_rng::SimRng, velocity_model::sample_launch, mass_model::{sample_masses, cube_size}};
 
 #[derive(Component)]
 pub struct Fragment;
 
+// Stable index of a fragment, assigned in the order fragments are generated
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FragmentId(pub u32);
+
+// Physical properties of a single fragment
+#[derive(Component)]
+pub struct FragmentProperties {
+    // Mass in kg
+    pub mass: f32,
+    // Edge length of the cube in m
+    pub size: f32,
+}
+
+// Sent on the update a fragment leaves the tracking radius, it is removed at the end of that update
+pub struct FragmentEscaped(pub Entity);
+
+// Number of fragments removed for leaving the tracking radius during the current simulation
+#[derive(Default)]
+pub struct EscapedFragments(pub u32);
+
 // Generate all fragments
 pub fn generate_fragments(
     grenade_data: Res<GrenadeData>,
     mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut materials: Option<ResMut<Assets<StandardMaterial>>>,
     sim_settings: Res<SimSettings>,
+    terrain: Option<Res<Terrain>>,
+    mut sim_rng: ResMut<SimRng>,
     mut commands: Commands
 ) {
-    // Remove data file if one exists
-    let res = fs::remove_file(sim_settings.csv_location.clone());
+    // No fragment has escaped yet
+    commands.insert_resource(EscapedFragments::default());
 
-    // If it does not exist fail quietly.
-    if res.is_err() {
-        println!("Could not delete file.")
-    }
+    // Use the seeded random-number-generator
+    let rng = &mut sim_rng.rng;
 
-    // Iterate through the size of fragment count
-    for _ in 0..sim_settings.fragment_count {
+    // Split the casing into fragments
+    let masses = sample_masses(&sim_settings, rng);
 
-        // Initialize a random-number-generator
-        let mut rng = thread_rng();
+    // Iterate through every fragment mass
+    for (index, mass) in masses.into_iter().enumerate() {
 
-        // Calculate random offsets from grenade
-        let offset_x: f32 = rng.gen_range(-100..100) as f32 / 100.0;
-        let offset_y: f32 = rng.gen_range(-100..100) as f32 / 100.0;
-        let offset_z: f32 = rng.gen_range(-100..100) as f32 / 100.0;
+        // Size the cube so it has the right mass
+        let size = cube_size(mass, sim_settings.fragment_density);
 
-        // Calculate velocity from the offsets
-        let vel_x: f32 = grenade_data.last_vel.linvel.x + (sim_settings.explosion_vel * offset_x);
-        let vel_y: f32 = grenade_data.last_vel.linvel.y + (sim_settings.explosion_vel * offset_y);
-        let vel_z: f32 = grenade_data.last_vel.linvel.z + (sim_settings.explosion_vel * offset_z);
+        // Draw the offset from the grenade and the launch velocity
+        let (offset, launch_vel) = sample_launch(&sim_settings, rng);
 
-        // Set positions
-        let mut x_pos: f32 = grenade_data.last_location.translation.x + offset_x;
-        let mut y_pos: f32 = grenade_data.last_location.translation.y + offset_y;
-        let mut z_pos: f32 = grenade_data.last_location.translation.z + offset_z;
+        // Add the launch velocity to the grenade velocity
+        let vel = grenade_data.last_vel.linvel + launch_vel;
 
-        // Ensure that they stay contained inside the box
-        if x_pos < 0.0 {
-            x_pos = 0.0;
-        } else if x_pos > 100.0 {
-            x_pos = 100.0;
-        }
-        if y_pos < 0.0 {
-            y_pos = 0.0;
-        }
-        if z_pos < 0.0 {
-            z_pos = 0.0;
-        } else if z_pos > 100.0 {
-            z_pos = 100.0;
-        }
+        // Set positions and ensure that they stay contained inside the arena
+        let position = grenade_data.last_location.translation + offset;
+        let position = sim_settings.arena.clamp(position, ground_height(terrain.as_deref(), position));
 
         // Spawn a fragment
-        commands.spawn_bundle(PbrBundle {
-            
-            // Set a mesh for the fragment in the shape of a cube
-            mesh: meshes.add(Mesh::from(shape::Cube {size: 0.05})),
+        let mut fragment = commands.spawn();
 
-            // Set the color to red
-            material: materials.add(Color::rgb(1.0, 0.0, 0.0).into()),
+        // Only give the fragment a mesh when rendering
+        if let Some(materials) = materials.as_mut() {
+            fragment.insert_bundle(PbrBundle {
 
-            // Initialize all other functions to their defaults
-            ..default()
-        })
+                // Set a mesh for the fragment in the shape of a cube
+                mesh: meshes.add(Mesh::from(shape::Cube {size})),
+
+                // Set the color to red
+                material: materials.add(Color::rgb(1.0, 0.0, 0.0).into()),
+
+                // Initialize all other functions to their defaults
+                ..default()
+            });
+        }
 
         // Add component to each fragment
-        .insert(Fragment)
+        fragment.insert(Fragment)
+
+        // Give each fragment its ID
+        .insert(FragmentId(index as u32))
+
+        // Keep track of mass and size
+        .insert(FragmentProperties {mass, size})
 
         // Add component for physics engine calculations
         .insert(RigidBody::Dynamic)
@@ -83,70 +94,51 @@ pub fn generate_fragments(
         // Set initial velocity to the last velocity linearly
         // TODO: Add random angular velocity
         .insert(Velocity {
-            linvel: Vec3::new(vel_x, vel_y, vel_z),
+            linvel: vel,
             angvel: grenade_data.last_vel.angvel,
         })
 
         // Set a collider to the size of the mesh
-        .insert(Collider::cuboid(0.1, 0.1, 0.1))
+        .insert(Collider::cuboid(size / 2.0, size / 2.0, size / 2.0))
+
+        // Sweep fast fragments between steps so they cannot pass through the floor or walls
+        .insert(Ccd { enabled: sim_settings.ccd })
+
+        // Report collisions so impacts can be logged
+        .insert(ActiveEvents::COLLISION_EVENTS)
 
         // Set fricition to value from SimSettings
         .insert(Friction::coefficient(sim_settings.friction))
 
-        // Set high density
-        .insert(ColliderMassProperties::Density(100.0))
+        // Set the density of the casing so the collider has the fragment mass
+        .insert(ColliderMassProperties::Density(sim_settings.fragment_density))
+
+        // Add a force for air resistance
+        .insert(ExternalForce::default())
 
         // Move to position around the grenade explosion location
-        .insert_bundle(TransformBundle::from(Transform::from_xyz(x_pos, y_pos, z_pos)));
+        .insert_bundle(TransformBundle::from(Transform::from_translation(position)));
     }
 }
 
-// Export data to csv during simulation
-pub fn write_fragment_data(
-    fragments: Query<(&Transform, &Velocity), With<Fragment>>,
-    time: Res<Time>,
-    sim_settings: Res<SimSettings>
+// Stop simulating fragments that left the tracking radius, announcing them so they can still be logged
+pub fn remove_escaped_fragments(
+    fragments: Query<(Entity, &Transform), With<Fragment>>,
+    grenade_data: Res<GrenadeData>,
+    sim_settings: Res<SimSettings>,
+    mut escaped_fragments: ResMut<EscapedFragments>,
+    mut escaped: EventWriter<FragmentEscaped>,
+    mut commands: Commands
 ) {
-    // Try writing to file
-    let result = write_to_file(fragments, time, sim_settings.csv_location.clone());
+    let detonation = grenade_data.last_location.translation;
 
-    // Handle error
-    if result.is_err() {
-        println!("Could not output data to file.")
-    }
-}
-
-fn write_to_file(fragments: Query<(&Transform, &Velocity), With<Fragment>>, time: Res<Time>, path: String) -> Result<(), Box<dyn Error>>{
-    // Open file as editable and create it if not created
-    let file = fs::OpenOptions::new()
-        .write(true)
-        .create(true)
-        .append(true)
-        .open(path)
-        .unwrap();
-    
-    // Create csv writer
-    let mut wtr = Writer::from_writer(file);
-
-    // Create blank vector to store row data
-    let mut record = vec![];
-
-    // Add the time since last update
-    record.push(format!("{}", time.delta().as_millis()));
-
-    // Iterate through every fragment and store the pos and vel at every update
-    for (pos, vel) in fragments.iter() {
-        record.push(format!("XPos:{}|Ypos:{}|Zpos:{}|Xvel:{}|Yvel:{}|Zvel:{}", pos.translation.x, pos.translation.y, pos.translation.z, vel.linvel.x, vel.linvel.y, vel.linvel.z));
+    for (fragment, transform) in fragments.iter() {
+        if sim_settings.arena.has_escaped(transform.translation, detonation) {
+            escaped.send(FragmentEscaped(fragment));
+            escaped_fragments.0 += 1;
+            commands.entity(fragment).despawn_recursive();
+        }
     }
-
-    // Write to file
-    wtr.write_record(record)?;
-
-    // Flush all changes to file and clear cache
-    wtr.flush()?;
-
-    // Return successful completion
-    Ok(())
 }
 
 // Remove fragments when simulation ended
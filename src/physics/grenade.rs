@@ -1,10 +1,10 @@
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
-use bevy_flycam::FlyCam;
+use std::time::Duration;
 
 use crate::AppState;
 
-use super::{sim_settings::SimSettings, GrenadeState};
+use super::{sim_settings::SimSettings, terrain::{ground_height, Terrain}, GrenadeState};
 
 #[derive(Component)]
 pub struct Grenade;
@@ -13,20 +13,27 @@ pub struct Grenade;
 pub fn spawn_grenade(
     mut commands:Commands,
     mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    sim_settings: Res<SimSettings>
+    materials: Option<ResMut<Assets<StandardMaterial>>>,
+    sim_settings: Res<SimSettings>,
+    terrain: Option<Res<Terrain>>
 ) {
-    let grenade = commands.spawn_bundle(PbrBundle {
-        // Create a mesh of a capsule
-        mesh: meshes.add(Mesh::from(shape::Capsule {radius: 0.2, depth: 0.2, ..default()})),
+    let mut grenade = commands.spawn();
 
-        // Set the color to red
-        material: materials.add(Color::rgb(1.0, 0.0, 0.0).into()),
+    // Only give the grenade a mesh when rendering
+    if let Some(mut materials) = materials {
+        grenade.insert_bundle(PbrBundle {
+            // Create a mesh of a capsule
+            mesh: meshes.add(Mesh::from(shape::Capsule {radius: 0.2, depth: 0.2, ..default()})),
 
-        // All other values as default
-        ..default()
-    })
-    
+            // Set the color to red
+            material: materials.add(Color::rgb(1.0, 0.0, 0.0).into()),
+
+            // All other values as default
+            ..default()
+        });
+    }
+
+    grenade
     // Insert Grenade identifier
     .insert(Grenade)
 
@@ -48,33 +55,18 @@ pub fn spawn_grenade(
     // Set resitution to SimSettings value
     .insert(Restitution::coefficient(sim_settings.restitution))
 
-    // Move to initial location
-    .insert_bundle(TransformBundle::from(Transform::from_xyz(0.0, sim_settings.initial_height, 0.0)))
-
-    // Return the id to be stored in the "grenade" variable
-    .id();
+    // Move to initial location, above the middle of the floor or terrain
+    .insert_bundle(TransformBundle::from(Transform::from_xyz(0.0, ground_height(terrain.as_deref(), Vec3::ZERO) + sim_settings.initial_height, 0.0)));
 
-
-    // Create a moveable camera
-    let camera = PerspectiveCameraBundle {
-        
-        // Move camera to initial position and point at the center
-        transform: Transform::from_xyz(0.0, 5.0, -5.0).looking_at(Vec3::ZERO, Vec3::Y),
-
-        // Set everything else to default
-        ..default()
-    };
-
-    // Add camera to world
-    let camera_entity = commands.spawn_bundle(camera).insert(FlyCam).id();
+    // Store the id in the "grenade" variable
+    let grenade = grenade.id();
 
     // Insert timer with duration from SimSettings
     commands.insert_resource(GrenadeTimer(Timer::from_seconds(sim_settings.fuse_time, false)));
 
-    // Save grenade id, camera id, that a grenade has been spawned, and the initial velocity and position for later use 
+    // Save grenade id, that a grenade has been spawned, and the initial velocity and position for later use 
     commands.insert_resource(GrenadeData {
         grenade,
-        camera: camera_entity,
         grenade_spawned: true,
         last_location: Transform {..default()},
         last_vel: Velocity {..default()}
@@ -84,7 +76,6 @@ pub fn spawn_grenade(
 // Data structure to keep track of grenade data
 pub struct GrenadeData {
     pub grenade: Entity,
-    pub camera: Entity,
     pub grenade_spawned: bool,
     pub last_location: Transform,
     pub last_vel: Velocity
@@ -103,14 +94,18 @@ pub fn explode_grenade(
     app_state: ResMut<State<AppState>>,
     mut grenade_state: ResMut<State<GrenadeState>>,
     mut grenade_data: ResMut<GrenadeData>,
+    sim_settings: Res<SimSettings>,
     asset_server: Res<AssetServer>,
-    audio: Res<Audio>
+    audio: Option<Res<Audio>>
 ) {
     // Ensure that still in simulation
     if *app_state.current() == AppState::LiveSim {
         
+        // Advance the fuse by simulated time, not frame time
+        let step = Duration::from_secs_f32(sim_settings.step_seconds(&time));
+
         // Check if timer is finished and grenade can be found
-        if timer.0.tick(time.delta()).just_finished() && !query.is_empty(){
+        if timer.0.tick(step).just_finished() && !query.is_empty(){
 
             // Extract grenade from world
             let (entity, _grenade) = query.single_mut();
@@ -118,8 +113,10 @@ pub fn explode_grenade(
             // If grenade is currently spawned
             if grenade_data.grenade_spawned {
 
-                // Play explosion audio effect
-                play_explosion(asset_server, audio);
+                // Play explosion audio effect if there is audio output
+                if let Some(audio) = audio {
+                    play_explosion(asset_server, audio);
+                }
 
                 // Get the position and velocity of the grenade
                 let (pos, vel) = grenade_cur.single();
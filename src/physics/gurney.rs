@@ -0,0 +1,70 @@
+use serde::Serialize;
+use std::str::FromStr;
+
+// Shape of the explosive charge and casing used by the Gurney equations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CasingGeometry {
+    Cylinder,
+    Sphere,
+}
+
+impl FromStr for CasingGeometry {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "cylinder" => Ok(CasingGeometry::Cylinder),
+            "sphere" => Ok(CasingGeometry::Sphere),
+            other => Err(format!("Unknown casing_geometry {}, expected cylinder or sphere.", other)),
+        }
+    }
+}
+
+// Initial casing velocity from the Gurney equations
+//
+// charge_mass and casing_mass are in kg, gurney_constant is sqrt(2E) in m/s.
+pub fn gurney_velocity(charge_mass: f32, casing_mass: f32, gurney_constant: f32, geometry: CasingGeometry) -> f32 {
+    // Casing to charge mass ratio
+    let ratio = casing_mass / charge_mass;
+
+    // Geometry dependent term
+    let shape_term = match geometry {
+        CasingGeometry::Cylinder => 1.0 / 2.0,
+        CasingGeometry::Sphere => 3.0 / 5.0,
+    };
+
+    gurney_constant / (ratio + shape_term).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1.0e-3 * expected, "expected {}, got {}", expected, actual);
+    }
+
+    #[test]
+    fn cylinder_and_sphere_constants() {
+        // V / sqrt(2E) = (M/C + 1/2)^-1/2 for a cylinder and (M/C + 3/5)^-1/2 for a sphere
+        assert_close(gurney_velocity(1.0, 1.0, 1.0, CasingGeometry::Cylinder), 0.8165);
+        assert_close(gurney_velocity(1.0, 1.0, 1.0, CasingGeometry::Sphere), 0.7906);
+        assert_close(gurney_velocity(0.5, 1.0, 2700.0, CasingGeometry::Cylinder), 2700.0 / 2.5_f32.sqrt());
+        assert_close(gurney_velocity(0.5, 1.0, 2700.0, CasingGeometry::Sphere), 2700.0 / 2.6_f32.sqrt());
+    }
+
+    #[test]
+    fn light_casing_limits() {
+        // Without a casing the velocity goes to sqrt(2) and sqrt(5/3) times the Gurney constant
+        assert_close(gurney_velocity(1.0, 1.0e-6, 2700.0, CasingGeometry::Cylinder), 2700.0 * 2.0_f32.sqrt());
+        assert_close(gurney_velocity(1.0, 1.0e-6, 2700.0, CasingGeometry::Sphere), 2700.0 * (5.0_f32 / 3.0).sqrt());
+    }
+
+    #[test]
+    fn geometry_names() {
+        assert_eq!(" Cylinder ".parse(), Ok(CasingGeometry::Cylinder));
+        assert_eq!("sphere".parse(), Ok(CasingGeometry::Sphere));
+        assert!("cube".parse::<CasingGeometry>().is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use rand::{distributions::WeightedIndex, prelude::Distribution, Rng};
+use serde::Serialize;
+use std::{error::Error, fs, str::FromStr};
+
+use super::sim_settings::SimSettings;
+
+// Smallest relative mass drawn before scaling, keeps colliders from collapsing to a point
+const MIN_RELATIVE_MASS: f32 = 1.0e-6;
+
+// How fragment masses are distributed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MassDistribution {
+    // Every fragment has the same mass
+    Uniform,
+    // Mott distribution, N(m) ~ exp(-(m/mu)^(1/2))
+    Mott,
+    // Grady (linear exponential) distribution, N(m) ~ exp(-m/mu)
+    Grady,
+    // Masses drawn from the histogram file in mass_histogram
+    Histogram,
+}
+
+impl FromStr for MassDistribution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "uniform" => Ok(MassDistribution::Uniform),
+            "mott" => Ok(MassDistribution::Mott),
+            "grady" => Ok(MassDistribution::Grady),
+            "histogram" => Ok(MassDistribution::Histogram),
+            other => Err(format!("Unknown mass_distribution {}, expected uniform, mott, grady or histogram.", other)),
+        }
+    }
+}
+
+// Draw the mass of every fragment, scaled so they add up to casing_mass
+pub fn sample_masses(sim_settings: &SimSettings, rng: &mut impl Rng) -> Vec<f32> {
+    let count = sim_settings.fragment_count as usize;
+
+    // Draw relative masses, the scale of the distribution does not matter after normalizing
+    let relative: Vec<f32> = match sim_settings.mass_distribution {
+        MassDistribution::Uniform => vec![1.0; count],
+        MassDistribution::Mott => (0..count).map(|_| exponential(rng).powi(2)).collect(),
+        MassDistribution::Grady => (0..count).map(|_| exponential(rng)).collect(),
+        MassDistribution::Histogram => match sample_histogram(&sim_settings.mass_histogram, count, rng) {
+            Ok(masses) => masses,
+            Err(err) => {
+                println!("Could not read mass histogram {}: {}. Using uniform masses.", sim_settings.mass_histogram, err);
+                vec![1.0; count]
+            }
+        },
+    };
+
+    // Scale so the total equals the casing mass
+    let relative: Vec<f32> = relative.into_iter().map(|mass| mass.max(MIN_RELATIVE_MASS)).collect();
+    let total: f32 = relative.iter().sum();
+    relative.into_iter().map(|mass| mass * sim_settings.casing_mass / total).collect()
+}
+
+// Edge length of a cube of the given mass and density
+pub fn cube_size(mass: f32, density: f32) -> f32 {
+    (mass / density).cbrt()
+}
+
+// Draw from the standard exponential distribution
+fn exponential(rng: &mut impl Rng) -> f32 {
+    // Use 1 - u so the logarithm never sees zero
+    -(1.0 - rng.gen::<f32>()).ln()
+}
+
+// Draw masses from a csv histogram with "mass,count" rows
+fn sample_histogram(path: &str, count: usize, rng: &mut impl Rng) -> Result<Vec<f32>, Box<dyn Error>> {
+    let contents = fs::read_to_string(path)?;
+
+    let mut masses = vec![];
+    let mut weights = vec![];
+    for line in contents.lines() {
+        let cells: Vec<&str> = line.split(',').map(|cell| cell.trim()).collect();
+
+        // Skip a header or any other row that is not two numbers
+        if let [mass, weight] = cells[..] {
+            if let (Ok(mass), Ok(weight)) = (mass.parse::<f32>(), weight.parse::<f32>()) {
+                masses.push(mass);
+                weights.push(weight);
+            }
+        }
+    }
+
+    // Fails if there are no bins or no positive weights
+    let bins = WeightedIndex::new(&weights)?;
+    Ok((0..count).map(|_| masses[bins.sample(rng)]).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::{env, process};
+
+    fn settings(mass_distribution: MassDistribution) -> SimSettings {
+        SimSettings { mass_distribution, fragment_count: 500, casing_mass: 0.35, ..SimSettings::default() }
+    }
+
+    fn sample(sim_settings: &SimSettings, seed: u64) -> Vec<f32> {
+        sample_masses(sim_settings, &mut StdRng::seed_from_u64(seed))
+    }
+
+    #[test]
+    fn masses_add_up_to_casing_mass() {
+        for distribution in [MassDistribution::Uniform, MassDistribution::Mott, MassDistribution::Grady, MassDistribution::Histogram] {
+            let sim_settings = SimSettings { mass_histogram: "missing_histogram.csv".to_string(), ..settings(distribution) };
+            let masses = sample(&sim_settings, 7);
+            assert_eq!(masses.len(), 500);
+            assert!(masses.iter().all(|mass| *mass > 0.0), "{:?} drew a mass of 0", distribution);
+            assert!((masses.iter().sum::<f32>() - 0.35).abs() < 1.0e-4, "{:?} masses add up to {}", distribution, masses.iter().sum::<f32>());
+        }
+    }
+
+    #[test]
+    fn uniform_masses_are_equal() {
+        let masses = sample(&settings(MassDistribution::Uniform), 7);
+        assert!(masses.iter().all(|mass| (mass - 0.35 / 500.0).abs() < 1.0e-8));
+    }
+
+    #[test]
+    fn mott_and_grady_repeat_with_a_seed() {
+        for distribution in [MassDistribution::Mott, MassDistribution::Grady] {
+            let sim_settings = settings(distribution);
+            assert_eq!(sample(&sim_settings, 42), sample(&sim_settings, 42));
+            assert_ne!(sample(&sim_settings, 42), sample(&sim_settings, 43));
+        }
+    }
+
+    #[test]
+    fn histogram_masses_come_from_its_bins() {
+        let path = env::temp_dir().join(format!("frag-sim-histogram-{}.csv", process::id()));
+        fs::write(&path, "mass,count\n1.0,3\n2.0,1\n5.0,0\n").unwrap();
+        let sim_settings = SimSettings { mass_histogram: path.to_string_lossy().into_owned(), ..settings(MassDistribution::Histogram) };
+        let masses = sample(&sim_settings, 7);
+        fs::remove_file(&path).unwrap();
+
+        // Every mass is one of the bins with a count, scaled by the same factor
+        let smallest = masses.iter().cloned().fold(f32::MAX, f32::min);
+        assert!(masses.iter().all(|mass| (mass / smallest - 1.0).abs() < 1.0e-4 || (mass / smallest - 2.0).abs() < 1.0e-4));
+    }
+}
@@ -1,75 +1,126 @@
 use bevy::prelude::*;
 use bevy_flycam::NoCameraPlayerPlugin;
 use bevy_rapier3d::prelude::*;
-use crate::AppState;
+use crate::{AppState, output::OutputPlugin};
 
 use self::{grenade::GrenadeData, sim_settings::SimSettings, sim_setup::Wall};
 
+pub mod arena;
 pub mod grenade;
 pub mod sim_setup;
 pub mod sim_settings;
 pub mod fragment;
+pub mod drag;
+pub mod gurney;
+pub mod mass_model;
+pub mod obstacle;
+pub mod sim_end;
+pub mod sim_rng;
+pub mod terrain;
+pub mod velocity_model;
 
 // Create a plugin
-pub struct PhyiscsSimPlugin;
+pub struct PhyiscsSimPlugin {
+    // Run without a window, camera, audio or meshes
+    pub headless: bool,
+}
 
 impl Plugin for PhyiscsSimPlugin {
     fn build(&self, app: &mut App) {
         app
-            // Add variable to keep track if cursor is locked
-            .insert_resource(CursorLocked(true))
-
-            // Add default SimSettings
-            .insert_resource(SimSettings {
-                fuse_time: 5.0,
-                lin_vel: Vec3::new(7.0, 2.0, 7.0),
-                ang_vel: Vec3::new(1.0, 2.0, 1.0),
-                friction: 10.0,
-                restitution: 0.9,
-                initial_height: 5.0,
-                fragment_count: 10,
-                explosion_vel: 480.0,
-                csv_location: "data.csv".to_string(),
-            })
+            // Add default SimSettings, replaced by the config file before each simulation starts
+            .init_resource::<SimSettings>()
 
             // Initialize to starting simulation as a grenade
             .add_state(GrenadeState::Grenade)
 
-            // When simulation is started try to fetch new settings from config file
-            .add_system_set(SystemSet::on_enter(AppState::LiveSim).with_system(sim_setup::update_sim_settings))
+            // Announce when the fragments are done
+            .add_event::<sim_end::SimulationEnded>()
 
-            // When simulation is started start cursor locking logic
-            .add_system_set(SystemSet::on_enter(AppState::LiveSim).with_system(lock_cursor))
+            // Announce and count fragments that left the tracking radius
+            .add_event::<fragment::FragmentEscaped>()
+            .init_resource::<fragment::EscapedFragments>()
+
+            // When simulation is started apply the physics timestep
+            .add_system_set(SystemSet::on_enter(AppState::LiveSim).with_system(sim_setup::apply_timestep))
+
+            // When simulation is started seed the random-number-generator
+            .add_system_set(SystemSet::on_enter(AppState::LiveSim).with_system(sim_rng::seed_sim_rng))
+
+            // When simulation is started let the physics engine handle fragments touching and sweeping through the terrain, if there is one
+            .add_system_set(SystemSet::on_enter(AppState::LiveSim).with_system(terrain::use_terrain_queries))
 
             // When simulation is started spawn the walls and floor
             .add_system_set(SystemSet::on_enter(AppState::LiveSim).with_system(sim_setup::spawn_solid_surfaces))
 
-            // When simulation is started spawn the ambient light
-            .add_system_set(SystemSet::on_enter(AppState::LiveSim).with_system(sim_setup::spawn_light))
-
             // When simulation is started spawn a grenade with the settings from SimSettings
-            .add_system_set(SystemSet::on_enter(AppState::LiveSim).with_system(grenade::spawn_grenade))
+            // Spawn it after the surfaces so colliders reach the physics engine in the same order every run
+            .add_system_set(SystemSet::on_enter(AppState::LiveSim).with_system(grenade::spawn_grenade.after(sim_setup::spawn_solid_surfaces)))
 
             // While simulation is running check to see if it is time to explode grenade
             .add_system_set(SystemSet::on_update(AppState::LiveSim).with_system(grenade::explode_grenade))
 
-            // While simulation is running check to see if "Q" is pressed to return to main menu
-            .add_system_set(SystemSet::on_update(AppState::LiveSim).with_system(back_to_main_menu_controls))
-
-            // When simulation is ending despawn grenade and camera
+            // When simulation is ending despawn grenade and walls
             .add_system_set(SystemSet::on_exit(AppState::LiveSim).with_system(cleanup))
 
+            // When entering the fragment part of the simulation move the open field ground under the detonation
+            .add_system_set(SystemSet::on_enter(GrenadeState::Fragment).with_system(sim_setup::center_ground))
+
             // When entering the fragment part of the simulation generate all fragmentss 
             .add_system_set(SystemSet::on_enter(GrenadeState::Fragment).with_system(fragment::generate_fragments))
 
-            // While in the fragment part of simulation, export path of frag
-            .add_system_set(SystemSet::on_update(GrenadeState::Fragment).with_system(fragment::write_fragment_data))
+            // When entering the fragment part of the simulation set up air resistance
+            .add_system_set(SystemSet::on_enter(GrenadeState::Fragment).with_system(drag::setup_drag))
+
+            // While in the fragment part of simulation, push back on fragments with air resistance
+            .add_system_set(SystemSet::on_update(GrenadeState::Fragment).with_system(drag::apply_drag))
+
+            // While in the fragment part of simulation, stop simulating fragments that left the tracking radius
+            .add_system_set(SystemSet::on_update(GrenadeState::Fragment).with_system(fragment::remove_escaped_fragments))
+
+            // When entering the fragment part of the simulation restart the simulation clock
+            .add_system_set(SystemSet::on_enter(GrenadeState::Fragment).with_system(sim_end::reset_sim_clock))
+
+            // While in the fragment part of simulation, advance the clock, check if the simulation is over and report fragments that left the arena
+            .add_system_set(
+                SystemSet::on_update(GrenadeState::Fragment)
+                    .with_system(sim_end::advance_sim_clock.before(sim_end::check_end_conditions))
+                    .with_system(sim_end::check_end_conditions)
+                    .with_system(sim_end::report_out_of_bounds.after(sim_end::check_end_conditions))
+            )
 
             // When fragmentation simulation is completed clean the fragments
             .add_system_set(SystemSet::on_exit(GrenadeState::Fragment).with_system(fragment::clean_fragments))
 
+            // Add data export
+            .add_plugin(OutputPlugin)
+
             // Add physics engine
-            .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
+            .add_plugin(RapierPhysicsPlugin::<NoUserData>::default());
+
+        // Everything below needs a window
+        if self.headless {
+            return;
+        }
+
+        app
+            // When simulation is started start cursor locking logic
+            .add_system_set(SystemSet::on_enter(AppState::LiveSim).with_system(lock_cursor))
+
+            // When simulation is started spawn the ambient light
+            .add_system_set(SystemSet::on_enter(AppState::LiveSim).with_system(sim_setup::spawn_light))
+
+            // When simulation is started spawn the moveable camera
+            .add_system_set(SystemSet::on_enter(AppState::LiveSim).with_system(sim_setup::spawn_camera))
+
+            // While simulation is running check to see if "Q" is pressed to return to main menu
+            .add_system_set(SystemSet::on_update(AppState::LiveSim).with_system(back_to_main_menu_controls))
+
+            // When the fragments are done return to main menu
+            .add_system_set(SystemSet::on_update(AppState::LiveSim).with_system(back_to_main_menu_on_end))
+
+            // When simulation is ending despawn the camera
+            .add_system_set(SystemSet::on_exit(AppState::LiveSim).with_system(sim_setup::despawn_camera))
 
             // Add camera handling
             .add_plugin(NoCameraPlayerPlugin);
@@ -83,12 +134,8 @@ pub enum GrenadeState {
     Fragment,
 }
 
-// Manage if cursor is locked
-#[derive(Component)]
-struct CursorLocked(bool);
-
 // Handle cursor locking
-fn lock_cursor(
+pub fn lock_cursor(
     mut windows: ResMut<Windows>,
     btn: Res<Input<MouseButton>>,
     key: Res<Input<KeyCode>>,
@@ -122,10 +169,7 @@ fn back_to_main_menu_controls(
         if keys.just_pressed(KeyCode::Q) {
             
             // Set state back to main menu
-            app_state.set(AppState::MainMenu).unwrap();
-
-            // Reset simulation to grenade
-            let _grenade_res = grenade_state.set(GrenadeState::Grenade);
+            return_to_main_menu(&mut app_state, &mut grenade_state);
 
             // Reset the "Q" stroke instantly
             keys.reset(KeyCode::Q);
@@ -133,7 +177,27 @@ fn back_to_main_menu_controls(
     }
 }
 
-// Remove grenade and camers
+// Return to main menu once the simulation has ended
+fn back_to_main_menu_on_end(
+    mut ended: EventReader<sim_end::SimulationEnded>,
+    mut app_state: ResMut<State<AppState>>,
+    mut grenade_state: ResMut<State<GrenadeState>>
+) {
+    if ended.iter().next().is_some() && *app_state.current() == AppState::LiveSim {
+        return_to_main_menu(&mut app_state, &mut grenade_state);
+    }
+}
+
+// Leave the simulation and reset it to grenade
+fn return_to_main_menu(app_state: &mut State<AppState>, grenade_state: &mut State<GrenadeState>) {
+    // Set state back to main menu
+    app_state.set(AppState::MainMenu).unwrap();
+
+    // Reset simulation to grenade
+    let _grenade_res = grenade_state.set(GrenadeState::Grenade);
+}
+
+// Remove grenade and walls
 fn cleanup(mut commands: Commands, sim_data: Res<GrenadeData>, walls: Query<Entity, With<Wall>>) {
 
     // If grenade exists then despawn it
@@ -144,7 +208,4 @@ fn cleanup(mut commands: Commands, sim_data: Res<GrenadeData>, walls: Query<Enti
     for wall in walls.iter() {
         commands.entity(wall).despawn_recursive();
     }
-
-    // Despawn camera
-    commands.entity(sim_data.camera).despawn_recursive();
 }
@@ -0,0 +1,213 @@
+use bevy::{prelude::*, render::mesh::{Indices, PrimitiveTopology}};
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{error::Error, path::Path, str::FromStr};
+
+use crate::config::parse_setting;
+
+use super::sim_settings::SimSettings;
+
+// Static scene geometry loaded from a glTF or OBJ file and placed in the arena
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ObstacleSettings {
+    // Written in the hit column of impacts with the obstacle, defaults to the file name
+    pub name: String,
+    pub path: String,
+    pub position: Vec3,
+    // Degrees around x, y and z, applied in that order
+    pub rotation: Vec3,
+    pub scale: Vec3,
+    #[serde(deserialize_with = "parse_setting")]
+    pub collider: ObstacleCollider,
+}
+
+// How the shape of an obstacle is turned into a collider
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObstacleCollider {
+    // Exactly the triangles of the file, fragments can hit it from both sides
+    Trimesh,
+    // Split into convex pieces, slower to build but solid on the inside
+    ConvexDecomposition,
+}
+
+impl FromStr for ObstacleCollider {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "trimesh" => Ok(ObstacleCollider::Trimesh),
+            "convex_decomposition" => Ok(ObstacleCollider::ConvexDecomposition),
+            other => Err(format!("Unknown obstacle collider {}, expected trimesh or convex_decomposition.", other)),
+        }
+    }
+}
+
+impl Default for ObstacleSettings {
+    fn default() -> Self {
+        ObstacleSettings {
+            name: String::new(),
+            path: String::new(),
+            position: Vec3::ZERO,
+            rotation: Vec3::ZERO,
+            scale: Vec3::ONE,
+            collider: ObstacleCollider::Trimesh,
+        }
+    }
+}
+
+impl ObstacleSettings {
+    // Name given in the config, else the file name without its extension
+    pub fn name(&self) -> String {
+        if !self.name.is_empty() {
+            return self.name.clone();
+        }
+        Path::new(&self.path).file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default()
+    }
+
+    // Where the obstacle is placed, the scale is part of its geometry
+    pub fn transform(&self) -> Transform {
+        let rotation = self.rotation * std::f32::consts::PI / 180.0;
+        Transform::from_translation(self.position).with_rotation(Quat::from_euler(EulerRot::ZYX, rotation.z, rotation.y, rotation.x))
+    }
+
+    // Read the triangles of the file, scaled
+    pub fn load(&self) -> Result<Geometry, Box<dyn Error>> {
+        let mut geometry = Geometry::load(Path::new(&self.path))?;
+        for vertex in &mut geometry.vertices {
+            *vertex *= self.scale;
+        }
+        Ok(geometry)
+    }
+}
+
+// Obstacles of the arena with their triangles, read before a simulation starts
+pub struct Obstacles(pub Vec<(ObstacleSettings, Geometry)>);
+
+// Read the file of every obstacle in the arena, stopping at the first that can not be loaded
+pub fn load_obstacles(sim_settings: &SimSettings) -> Result<Obstacles, String> {
+    sim_settings.arena.obstacles
+        .iter()
+        .map(|obstacle| {
+            let geometry = obstacle.load().map_err(|err| format!("Could not load obstacle {}: {}", obstacle.path, err))?;
+            Ok((obstacle.clone(), geometry))
+        })
+        .collect::<Result<_, _>>()
+        .map(Obstacles)
+}
+
+// Whether a file can be loaded as an obstacle
+pub fn is_supported(path: &Path) -> bool {
+    matches!(extension(path).as_str(), "gltf" | "glb" | "obj")
+}
+
+fn extension(path: &Path) -> String {
+    path.extension().map(|ext| ext.to_string_lossy().to_lowercase()).unwrap_or_default()
+}
+
+// Triangles of an obstacle, the render mesh and the collider are both built from them
+pub struct Geometry {
+    pub vertices: Vec<Vec3>,
+    pub indices: Vec<[u32; 3]>,
+}
+
+impl Geometry {
+    // Read every triangle of a glTF or OBJ file
+    pub fn load(path: &Path) -> Result<Geometry, Box<dyn Error>> {
+        let mut geometry = Geometry { vertices: vec![], indices: vec![] };
+        match extension(path).as_str() {
+            "gltf" | "glb" => geometry.read_gltf(path)?,
+            "obj" => geometry.read_obj(path)?,
+            _ => return Err("only .gltf, .glb and .obj files can be loaded".into()),
+        }
+
+        if geometry.indices.is_empty() {
+            return Err("the file has no triangles".into());
+        }
+        Ok(geometry)
+    }
+
+    // Add the triangles of every mesh in the default scene, moved by the transforms of their nodes
+    fn read_gltf(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        // Only the buffers are needed, textures are never decoded
+        let gltf = gltf::Gltf::open(path)?;
+        let buffers = gltf::import_buffers(&gltf.document, path.parent(), gltf.blob.clone())?;
+
+        let scene = gltf.document.default_scene().or_else(|| gltf.document.scenes().next()).ok_or("the file has no scene")?;
+        let mut nodes: Vec<(gltf::Node, Mat4)> = scene.nodes().map(|node| (node, Mat4::IDENTITY)).collect();
+
+        while let Some((node, parent)) = nodes.pop() {
+            let transform = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+
+            if let Some(mesh) = node.mesh() {
+                for primitive in mesh.primitives().filter(|primitive| primitive.mode() == gltf::mesh::Mode::Triangles) {
+                    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+                    let positions = match reader.read_positions() {
+                        Some(positions) => positions,
+                        None => continue,
+                    };
+
+                    let start = self.vertices.len() as u32;
+                    self.vertices.extend(positions.map(|position| transform.transform_point3(Vec3::from(position))));
+
+                    // Primitives without indices list their vertices in triangle order
+                    let indices: Vec<u32> = match reader.read_indices() {
+                        Some(indices) => indices.into_u32().collect(),
+                        None => (0..self.vertices.len() as u32 - start).collect(),
+                    };
+                    self.indices.extend(indices.chunks_exact(3).map(|triangle| [start + triangle[0], start + triangle[1], start + triangle[2]]));
+                }
+            }
+
+            nodes.extend(node.children().map(|child| (child, transform)));
+        }
+
+        Ok(())
+    }
+
+    // Add the triangles of every object in the file, polygons are split into triangles
+    fn read_obj(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let options = tobj::LoadOptions { triangulate: true, single_index: true, ..Default::default() };
+        let (models, _) = tobj::load_obj(path, &options)?;
+
+        for model in models {
+            let start = self.vertices.len() as u32;
+            self.vertices.extend(model.mesh.positions.chunks_exact(3).map(|position| Vec3::new(position[0], position[1], position[2])));
+            self.indices.extend(model.mesh.indices.chunks_exact(3).map(|triangle| [start + triangle[0], start + triangle[1], start + triangle[2]]));
+        }
+
+        Ok(())
+    }
+
+    // Mesh for rendering, every triangle gets its own vertices so it is lit as a flat face
+    pub fn mesh(&self) -> Mesh {
+        let mut positions = Vec::with_capacity(self.indices.len() * 3);
+        let mut normals = Vec::with_capacity(self.indices.len() * 3);
+
+        for triangle in &self.indices {
+            let [a, b, c] = triangle.map(|index| self.vertices[index as usize]);
+            let normal = (b - a).cross(c - a).normalize_or_zero();
+            for vertex in [a, b, c] {
+                positions.push(vertex.to_array());
+                normals.push(normal.to_array());
+            }
+        }
+
+        let uvs = vec![[0.0, 0.0]; positions.len()];
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.set_indices(Some(Indices::U32((0..positions.len() as u32).collect())));
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh
+    }
+
+    // Collider of the chosen kind
+    pub fn collider(&self, kind: ObstacleCollider) -> Collider {
+        match kind {
+            ObstacleCollider::Trimesh => Collider::trimesh(self.vertices.clone(), self.indices.clone()),
+            ObstacleCollider::ConvexDecomposition => Collider::convex_decomposition(&self.vertices, &self.indices),
+        }
+    }
+}
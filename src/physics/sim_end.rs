@@ -0,0 +1,115 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use serde::Serialize;
+use std::fmt;
+
+use super::{arena::ArenaPreset, fragment::Fragment, sim_settings::SimSettings, terrain::{ground_height, Terrain}};
+
+// Simulated time and steps since the grenade exploded
+#[derive(Default)]
+pub struct SimClock {
+    pub elapsed: f32,
+    pub steps: u32,
+    // Whether the end of the simulation has been announced
+    pub ended: bool,
+}
+
+// Why the simulation stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EndReason {
+    AtRest,
+    MaxTime,
+    MaxSteps,
+}
+
+impl fmt::Display for EndReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EndReason::AtRest => write!(f, "all fragments at rest"),
+            EndReason::MaxTime => write!(f, "reached max_sim_time"),
+            EndReason::MaxSteps => write!(f, "reached max_steps"),
+        }
+    }
+}
+
+// Event sent once when the fragment simulation is finished
+#[derive(Clone, Copy)]
+pub struct SimulationEnded {
+    pub reason: EndReason,
+    pub elapsed: f32,
+    pub steps: u32,
+}
+
+// Restart the clock when the fragments are created
+pub fn reset_sim_clock(mut commands: Commands) {
+    commands.insert_resource(SimClock::default());
+}
+
+// Advance the clock by one physics step every update
+pub fn advance_sim_clock(mut clock: ResMut<SimClock>, time: Res<Time>, sim_settings: Res<SimSettings>) {
+    clock.elapsed += sim_settings.step_seconds(&time);
+    clock.steps += 1;
+}
+
+// Check the termination conditions from SimSettings and announce the end once
+pub fn check_end_conditions(
+    mut clock: ResMut<SimClock>,
+    sim_settings: Res<SimSettings>,
+    fragments: Query<&Velocity, With<Fragment>>,
+    mut ended: EventWriter<SimulationEnded>,
+) {
+    if clock.ended {
+        return;
+    }
+
+    // Fragments only exist after the first step, so wait before checking for rest
+    let at_rest = sim_settings.rest_velocity > 0.0
+        && clock.steps > 1
+        && fragments.iter().all(|vel| vel.linvel.length() < sim_settings.rest_velocity);
+
+    // A limit of zero disables that condition
+    let reason = if at_rest {
+        Some(EndReason::AtRest)
+    } else if sim_settings.max_sim_time > 0.0 && clock.elapsed >= sim_settings.max_sim_time {
+        Some(EndReason::MaxTime)
+    } else if sim_settings.max_steps > 0 && clock.steps >= sim_settings.max_steps {
+        Some(EndReason::MaxSteps)
+    } else {
+        None
+    };
+
+    if let Some(reason) = reason {
+        clock.ended = true;
+        ended.send(SimulationEnded {
+            reason,
+            elapsed: clock.elapsed,
+            steps: clock.steps,
+        });
+    }
+}
+
+// Warn about fragments that ended outside the arena so tunneling does not go unnoticed
+pub fn report_out_of_bounds(
+    mut ended: EventReader<SimulationEnded>,
+    fragments: Query<&Transform, With<Fragment>>,
+    sim_settings: Res<SimSettings>,
+    terrain: Option<Res<Terrain>>,
+) {
+    if ended.iter().next().is_none() {
+        return;
+    }
+
+    let outside = fragments
+        .iter()
+        .filter(|transform| sim_settings.arena.is_outside(transform.translation, ground_height(terrain.as_deref(), transform.translation)))
+        .count();
+    if outside > 0 {
+        let hint = match (sim_settings.ccd, sim_settings.arena.preset) {
+            (false, _) => "tunneled, try turning ccd on",
+            (true, ArenaPreset::Room) => "flew over a wall or tunneled, try a smaller timestep",
+            (true, ArenaPreset::OpenField | ArenaPreset::None) => "tunneled, try a smaller timestep",
+        };
+        println!("{} of {} fragments ended outside the arena, they probably {}.", outside, fragments.iter().count(), hint);
+    }
+}
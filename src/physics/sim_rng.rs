@@ -0,0 +1,23 @@
+use bevy::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
+
+use super::sim_settings::SimSettings;
+
+// Random-number-generator shared by everything random in a simulation
+pub struct SimRng {
+    pub seed: u64,
+    pub rng: StdRng,
+}
+
+// Seed the generator from SimSettings, picking a random seed if none was given
+pub fn seed_sim_rng(mut commands: Commands, sim_settings: Res<SimSettings>) {
+    let seed = sim_settings.seed.unwrap_or_else(rand::random);
+
+    // Print the seed so the run can be repeated
+    println!("Using seed {}.", seed);
+
+    commands.insert_resource(SimRng {
+        seed,
+        rng: StdRng::seed_from_u64(seed),
+    });
+}
@@ -1,6 +1,15 @@
-use bevy::math::Vec3;
+use bevy::{core::Time, math::Vec3};
+use bevy_rapier3d::plugin::TimestepMode;
+use serde::{Deserialize, Serialize};
+
+use crate::{config::{parse_setting, parse_optional_setting}, output::{OutputFormat, csv_output::OutputLayout, data_file::OutputMode}};
+
+use super::{arena::ArenaSettings, gurney::{gurney_velocity, CasingGeometry}, mass_model::MassDistribution, velocity_model::{DirectionModel, SpeedModel}};
 
 // Set up data structure for handling all simulation settings. 
+// Settings missing from a config file keep their value from Default
+#[derive(Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct SimSettings {
     pub fuse_time: f32,
     pub lin_vel: Vec3,
@@ -8,7 +17,131 @@ pub struct SimSettings {
     pub friction: f32,
     pub restitution: f32,
     pub initial_height: f32,
+    pub arena: ArenaSettings,
     pub fragment_count: u32,
-    pub explosion_vel: f32,
+    pub explosion_vel: Option<f32>,
+    pub charge_mass: Option<f32>,
+    pub casing_mass: f32,
+    pub gurney_constant: f32,
+    #[serde(deserialize_with = "parse_setting")]
+    pub casing_geometry: CasingGeometry,
+    #[serde(deserialize_with = "parse_setting")]
+    pub mass_distribution: MassDistribution,
+    pub mass_histogram: String,
+    pub fragment_density: f32,
+    pub drag: bool,
+    pub air_density: f32,
+    pub drag_coefficient: f32,
+    pub drag_table: String,
+    pub speed_of_sound: f32,
+    #[serde(deserialize_with = "parse_setting")]
+    pub output_layout: OutputLayout,
+    #[serde(deserialize_with = "parse_optional_setting")]
+    pub output_format: Option<OutputFormat>,
+    pub writer_thread: bool,
+    #[serde(deserialize_with = "parse_setting")]
+    pub output_mode: OutputMode,
+    pub run_directories: bool,
+    pub log_interval: f32,
+    pub log_every_n_steps: u32,
+    pub log_events_only: bool,
     pub csv_location: String,
-}
\ No newline at end of file
+    pub max_sim_time: f32,
+    pub rest_velocity: f32,
+    pub max_steps: u32,
+    pub seed: Option<u64>,
+    pub timestep: f32,
+    pub substeps: usize,
+    pub ccd: bool,
+    #[serde(deserialize_with = "parse_setting")]
+    pub direction_model: DirectionModel,
+    #[serde(deserialize_with = "parse_setting")]
+    pub speed_model: SpeedModel,
+    pub speed_std: f32,
+    pub speed_min: f32,
+    pub speed_max: f32,
+}
+
+impl Default for SimSettings {
+    fn default() -> Self {
+        SimSettings {
+            fuse_time: 5.0,
+            lin_vel: Vec3::new(7.0, 2.0, 7.0),
+            ang_vel: Vec3::new(1.0, 2.0, 1.0),
+            friction: 10.0,
+            restitution: 0.9,
+            initial_height: 5.0,
+            arena: ArenaSettings::default(),
+            fragment_count: 100,
+            explosion_vel: None,
+            charge_mass: None,
+            casing_mass: 0.2,
+            gurney_constant: 2700.0,
+            casing_geometry: CasingGeometry::Sphere,
+            mass_distribution: MassDistribution::Mott,
+            mass_histogram: "mass_histogram.csv".to_string(),
+            fragment_density: 7850.0,
+            drag: true,
+            air_density: 1.225,
+            drag_coefficient: 1.2,
+            drag_table: String::new(),
+            speed_of_sound: 343.0,
+            output_layout: OutputLayout::Long,
+            output_format: None,
+            writer_thread: false,
+            output_mode: OutputMode::AutoIncrement,
+            run_directories: false,
+            log_interval: 0.0,
+            log_every_n_steps: 1,
+            log_events_only: false,
+            csv_location: "data.csv".to_string(),
+            max_sim_time: 30.0,
+            rest_velocity: 0.1,
+            max_steps: 0,
+            seed: None,
+            timestep: 1.0 / 60.0,
+            substeps: 1,
+            ccd: true,
+            direction_model: DirectionModel::Sphere,
+            speed_model: SpeedModel::Constant,
+            speed_std: 50.0,
+            speed_min: 400.0,
+            speed_max: 560.0,
+        }
+    }
+}
+
+impl SimSettings {
+    // Fragment launch speed, from explosion_vel if given, else from the Gurney equations if a charge is given
+    pub fn explosion_speed(&self) -> f32 {
+        match (self.explosion_vel, self.charge_mass) {
+            (Some(explosion_vel), _) => explosion_vel,
+            (None, Some(charge_mass)) => gurney_velocity(charge_mass, self.casing_mass, self.gurney_constant, self.casing_geometry),
+            (None, None) => DEFAULT_EXPLOSION_VEL,
+        }
+    }
+
+    // Physics timestep mode for rapier
+    pub fn timestep_mode(&self) -> TimestepMode {
+        if self.timestep > 0.0 {
+            TimestepMode::Fixed { dt: self.timestep, substeps: self.substeps.max(1) }
+        } else {
+            TimestepMode::Variable { max_dt: MAX_VARIABLE_TIMESTEP, time_scale: 1.0, substeps: self.substeps.max(1) }
+        }
+    }
+
+    // Simulated seconds that pass in one physics step
+    pub fn step_seconds(&self, time: &Time) -> f32 {
+        if self.timestep > 0.0 {
+            self.timestep
+        } else {
+            time.delta_seconds().min(MAX_VARIABLE_TIMESTEP)
+        }
+    }
+}
+
+// Launch speed when neither explosion_vel nor a charge is given
+const DEFAULT_EXPLOSION_VEL: f32 = 480.0;
+
+// Longest step allowed when stepping with the frame rate
+const MAX_VARIABLE_TIMESTEP: f32 = 1.0 / 60.0;
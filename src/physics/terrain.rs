@@ -0,0 +1,519 @@
+use bevy::{prelude::*, render::mesh::{Indices, PrimitiveTopology}};
+use bevy_rapier3d::{
+    parry::{
+        bounding_volume::{BoundingVolume, AABB},
+        math::{Isometry, Real, Vector},
+        query::{
+            ClosestPoints, Contact, ContactManifold, ContactManifoldsWorkspace, DefaultQueryDispatcher, NonlinearRigidMotion, PersistentQueryDispatcher, QueryDispatcher, Unsupported, TOI,
+        },
+        shape::{HeightField, RoundTriangle, Shape, Triangle},
+    },
+    prelude::*,
+    rapier::prelude::{CCDSolver, NarrowPhase},
+};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, path::Path};
+
+use super::sim_settings::SimSettings;
+
+// Rounded edge given to the triangles of the terrain when they touch a fragment, in m
+const CONTACT_RADIUS: f32 = 0.05;
+
+// How far below the heights continuous collision detection stops a fragment, in m
+// About the overlap the physics engine allows, so a stopped fragment is already touching the terrain when the contact takes over
+const SWEEP_DEPTH: f32 = 0.001;
+
+// Ground shaped by a height map, replacing the flat floor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TerrainSettings {
+    // Grayscale PNG or csv grid of heights, the floor stays flat when empty
+    pub path: String,
+    // Distance between neighbouring heights along x and z
+    pub cell_size: f32,
+    // Height of a white pixel, or the factor the csv values are multiplied by
+    pub vertical_scale: f32,
+}
+
+impl Default for TerrainSettings {
+    fn default() -> Self {
+        TerrainSettings {
+            path: String::new(),
+            cell_size: 1.0,
+            vertical_scale: 1.0,
+        }
+    }
+}
+
+// Whether a file can be loaded as a height map
+pub fn is_supported(path: &Path) -> bool {
+    matches!(extension(path).as_str(), "png" | "csv")
+}
+
+fn extension(path: &Path) -> String {
+    path.extension().map(|ext| ext.to_string_lossy().to_lowercase()).unwrap_or_default()
+}
+
+// Heights of the terrain centered on x = z = 0, rows run along z and columns along x
+// The collider, the mesh and height_at all split every cell into the same two triangles
+pub struct Terrain {
+    heights: Vec<f32>,
+    rows: usize,
+    cols: usize,
+    cell_size: f32,
+}
+
+impl Terrain {
+    // Read the heights from a PNG or csv file and scale them to meters
+    pub fn load(settings: &TerrainSettings) -> Result<Terrain, Box<dyn Error>> {
+        let path = Path::new(&settings.path);
+        let (heights, rows, cols) = match extension(path).as_str() {
+            "png" => read_png(path)?,
+            "csv" => read_csv(path)?,
+            _ => return Err("only .png and .csv files can be loaded".into()),
+        };
+
+        if rows < 2 || cols < 2 {
+            return Err(format!("the height map needs at least 2 x 2 heights, got {} x {}", cols, rows).into());
+        }
+
+        Ok(Terrain {
+            heights: heights.into_iter().map(|height| height * settings.vertical_scale).collect(),
+            rows,
+            cols,
+            cell_size: settings.cell_size,
+        })
+    }
+
+    fn height(&self, row: usize, col: usize) -> f32 {
+        self.heights[row * self.cols + col]
+    }
+
+    // Size of the terrain along x and z
+    pub fn size(&self) -> (f32, f32) {
+        ((self.cols - 1) as f32 * self.cell_size, (self.rows - 1) as f32 * self.cell_size)
+    }
+
+    // Position of a height in the world
+    fn vertex(&self, row: usize, col: usize) -> Vec3 {
+        let (width, depth) = self.size();
+        Vec3::new(col as f32 * self.cell_size - width / 2.0, self.height(row, col), row as f32 * self.cell_size - depth / 2.0)
+    }
+
+    // Lowest height of the terrain
+    pub fn lowest(&self) -> f32 {
+        self.heights.iter().copied().fold(f32::INFINITY, f32::min)
+    }
+
+    // Row and column of a point in the grid, fractional between heights
+    fn grid_position(&self, x: f32, z: f32) -> (f32, f32) {
+        let (width, depth) = self.size();
+        ((z + depth / 2.0) / self.cell_size, (x + width / 2.0) / self.cell_size)
+    }
+
+    // Height of the ground at a point, None beyond the edge of the terrain
+    pub fn height_at(&self, x: f32, z: f32) -> Option<f32> {
+        let (row, col) = self.grid_position(x, z);
+        if col < 0.0 || row < 0.0 || col > (self.cols - 1) as f32 || row > (self.rows - 1) as f32 {
+            return None;
+        }
+        Some(self.grid_height(row, col))
+    }
+
+    // Height of the ground at a point, or at the nearest point on the edge beyond the terrain
+    pub fn nearest_height(&self, x: f32, z: f32) -> f32 {
+        let (row, col) = self.grid_position(x, z);
+        self.grid_height(row.clamp(0.0, (self.rows - 1) as f32), col.clamp(0.0, (self.cols - 1) as f32))
+    }
+
+    fn grid_height(&self, row: f32, col: f32) -> f32 {
+        // Cell the point is in and where it is inside the cell
+        let (i, j) = ((row as usize).min(self.rows - 2), (col as usize).min(self.cols - 2));
+        let (u, v) = (col - j as f32, row - i as f32);
+        let (h00, h10, h01, h11) = (self.height(i, j), self.height(i + 1, j), self.height(i, j + 1), self.height(i + 1, j + 1));
+
+        // Interpolate on the triangle the point is on
+        if u + v <= 1.0 {
+            h00 + (h01 - h00) * u + (h10 - h00) * v
+        } else {
+            h11 + (h10 - h11) * (1.0 - u) + (h01 - h11) * (1.0 - v)
+        }
+    }
+
+    // Two triangles per cell facing up, split along the same diagonal as the heightfield collider
+    fn triangles(&self) -> Vec<[u32; 3]> {
+        let index = |row: usize, col: usize| (row * self.cols + col) as u32;
+        let mut triangles = Vec::with_capacity((self.rows - 1) * (self.cols - 1) * 2);
+        for row in 0..self.rows - 1 {
+            for col in 0..self.cols - 1 {
+                triangles.push([index(row, col), index(row + 1, col), index(row, col + 1)]);
+                triangles.push([index(row + 1, col), index(row + 1, col + 1), index(row, col + 1)]);
+            }
+        }
+        triangles
+    }
+
+    // Heightfield collider, which takes the heights column by column
+    pub fn collider(&self) -> Collider {
+        let heights = (0..self.cols).flat_map(|col| (0..self.rows).map(move |row| self.height(row, col))).collect();
+        let (width, depth) = self.size();
+        Collider::heightfield(heights, self.rows, self.cols, Vec3::new(width, 1.0, depth).into())
+    }
+
+    // Mesh for rendering with smooth normals
+    pub fn mesh(&self) -> Mesh {
+        let mut positions = Vec::with_capacity(self.heights.len());
+        let mut normals = Vec::with_capacity(self.heights.len());
+        let mut uvs = Vec::with_capacity(self.heights.len());
+
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                positions.push(self.vertex(row, col).to_array());
+
+                // Slope from the neighbouring heights
+                let dx = self.height(row, (col + 1).min(self.cols - 1)) - self.height(row, col.saturating_sub(1));
+                let dz = self.height((row + 1).min(self.rows - 1), col) - self.height(row.saturating_sub(1), col);
+                normals.push(Vec3::new(-dx, 2.0 * self.cell_size, -dz).normalize().to_array());
+
+                uvs.push([col as f32 / (self.cols - 1) as f32, row as f32 / (self.rows - 1) as f32]);
+            }
+        }
+
+        let indices = self.triangles().concat();
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.set_indices(Some(Indices::U32(indices)));
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh
+    }
+}
+
+// Height of the ground under a point, at the nearest edge beyond the terrain and 0 without one
+pub fn ground_height(terrain: Option<&Terrain>, position: Vec3) -> f32 {
+    terrain.map_or(0.0, |terrain| terrain.height_at(position.x, position.z).unwrap_or_else(|| terrain.nearest_height(position.x, position.z)))
+}
+
+// Queries against the terrain that parry either leaves out or gets wrong for fragments
+// It has no time of impact with a heightfield, so continuous collision detection let fast fragments through,
+// and the contacts of millimetre cuboids with bare triangles come and go, so resting fragments sank through
+struct TerrainQueries;
+
+impl TerrainQueries {
+    // Earliest impact of a shape with the triangles of a static heightfield along its path
+    fn time_of_impact(
+        motion1: &NonlinearRigidMotion,
+        heightfield: &HeightField,
+        motion2: &NonlinearRigidMotion,
+        g2: &dyn Shape,
+        start_time: Real,
+        end_time: Real,
+        stop_at_penetration: bool,
+    ) -> Option<TOI> {
+        // Box around the bounding sphere of the shape at both ends of the motion, in the space of the heightfield
+        let sphere = g2.compute_local_bounding_sphere();
+        let start = sphere.transform_by(&motion1.position_at_time(start_time).inv_mul(&motion2.position_at_time(start_time)));
+        let end = sphere.transform_by(&motion1.position_at_time(end_time).inv_mul(&motion2.position_at_time(end_time)));
+        let radius = Vector::repeat(sphere.radius());
+        let swept = AABB::new(start.center().inf(end.center()) - radius, start.center().sup(end.center()) + radius);
+
+        // Keep the first impact with any triangle the box touches
+        let mut first: Option<TOI> = None;
+        heightfield.map_elements_in_local_aabb(&swept, &mut |_, triangle| {
+            let end_time = first.map_or(end_time, |toi| toi.toi);
+            if let Ok(Some(toi)) = DefaultQueryDispatcher.nonlinear_time_of_impact(motion1, &TerrainQueries::sunk(triangle, SWEEP_DEPTH), motion2, g2, start_time, end_time, stop_at_penetration) {
+                if first.is_none_or(|first| toi.toi < first.toi) {
+                    first = Some(toi);
+                }
+            }
+        });
+        first
+    }
+
+    // Contacts of a shape with every triangle of a heightfield near it, one manifold per triangle
+    // The manifold of a triangle is kept from the last step, so resting fragments keep their contacts
+    fn contact_manifolds<ManifoldData: Default + Clone, ContactData: Default + Copy>(
+        pos12: &Isometry<Real>,
+        heightfield: &HeightField,
+        shape: &dyn Shape,
+        prediction: Real,
+        manifolds: &mut Vec<ContactManifold<ManifoldData, ContactData>>,
+        flipped: bool,
+    ) {
+        let mut last_manifolds = std::mem::take(manifolds);
+        let aabb = shape.compute_aabb(pos12).loosened(prediction);
+        heightfield.map_elements_in_local_aabb(&aabb, &mut |id, triangle| {
+            // Triangle sunk by the contact radius, so once rounded its face is back on the heights
+            let rounded = RoundTriangle { inner_shape: TerrainQueries::sunk(triangle, CONTACT_RADIUS), border_radius: CONTACT_RADIUS };
+
+            let last = last_manifolds.iter_mut().find(|manifold| if flipped { manifold.subshape2 == id } else { manifold.subshape1 == id });
+            let mut manifold = match last {
+                Some(manifold) => manifold.take(),
+                None if flipped => ContactManifold::with_data(0, id, ManifoldData::default()),
+                None => ContactManifold::with_data(id, 0, ManifoldData::default()),
+            };
+            let _ = if flipped {
+                DefaultQueryDispatcher.contact_manifold_convex_convex(&pos12.inverse(), shape, &rounded, prediction, &mut manifold)
+            } else {
+                DefaultQueryDispatcher.contact_manifold_convex_convex(pos12, &rounded, shape, prediction, &mut manifold)
+            };
+            manifolds.push(manifold);
+        });
+    }
+
+    // Triangle moved into the ground along its normal, so its face stays parallel to the heights at any slope
+    fn sunk(triangle: &Triangle, depth: f32) -> Triangle {
+        let normal = triangle.normal().map_or(Vector::y(), |normal| normal.into_inner());
+        let down = normal * depth * normal.y.signum();
+        Triangle::new(triangle.a - down, triangle.b - down, triangle.c - down)
+    }
+}
+
+impl QueryDispatcher for TerrainQueries {
+    fn intersection_test(&self, _: &Isometry<Real>, _: &dyn Shape, _: &dyn Shape) -> Result<bool, Unsupported> {
+        Err(Unsupported)
+    }
+
+    fn distance(&self, _: &Isometry<Real>, _: &dyn Shape, _: &dyn Shape) -> Result<Real, Unsupported> {
+        Err(Unsupported)
+    }
+
+    fn contact(&self, _: &Isometry<Real>, _: &dyn Shape, _: &dyn Shape, _: Real) -> Result<Option<Contact>, Unsupported> {
+        Err(Unsupported)
+    }
+
+    fn closest_points(&self, _: &Isometry<Real>, _: &dyn Shape, _: &dyn Shape, _: Real) -> Result<ClosestPoints, Unsupported> {
+        Err(Unsupported)
+    }
+
+    fn time_of_impact(&self, _: &Isometry<Real>, _: &Vector<Real>, _: &dyn Shape, _: &dyn Shape, _: Real) -> Result<Option<TOI>, Unsupported> {
+        Err(Unsupported)
+    }
+
+    fn nonlinear_time_of_impact(
+        &self,
+        motion1: &NonlinearRigidMotion,
+        g1: &dyn Shape,
+        motion2: &NonlinearRigidMotion,
+        g2: &dyn Shape,
+        start_time: Real,
+        end_time: Real,
+        stop_at_penetration: bool,
+    ) -> Result<Option<TOI>, Unsupported> {
+        if let Some(heightfield) = g1.as_heightfield() {
+            Ok(TerrainQueries::time_of_impact(motion1, heightfield, motion2, g2, start_time, end_time, stop_at_penetration))
+        } else if let Some(heightfield) = g2.as_heightfield() {
+            Ok(TerrainQueries::time_of_impact(motion2, heightfield, motion1, g1, start_time, end_time, stop_at_penetration).map(TOI::swapped))
+        } else {
+            Err(Unsupported)
+        }
+    }
+}
+
+impl<ManifoldData: Default + Clone, ContactData: Default + Copy> PersistentQueryDispatcher<ManifoldData, ContactData> for TerrainQueries {
+    // Contacts of a heightfield with a convex shape, against rounded triangles
+    fn contact_manifolds(
+        &self,
+        pos12: &Isometry<Real>,
+        g1: &dyn Shape,
+        g2: &dyn Shape,
+        prediction: Real,
+        manifolds: &mut Vec<ContactManifold<ManifoldData, ContactData>>,
+        _: &mut Option<ContactManifoldsWorkspace>,
+    ) -> Result<(), Unsupported> {
+        if g1.as_composite_shape().is_some() || g2.as_composite_shape().is_some() {
+            return Err(Unsupported);
+        }
+        if let Some(heightfield) = g1.as_heightfield() {
+            TerrainQueries::contact_manifolds(pos12, heightfield, g2, prediction, manifolds, false);
+        } else if let Some(heightfield) = g2.as_heightfield() {
+            TerrainQueries::contact_manifolds(&pos12.inverse(), heightfield, g1, prediction, manifolds, true);
+        } else {
+            return Err(Unsupported);
+        }
+        Ok(())
+    }
+
+    fn contact_manifold_convex_convex(&self, _: &Isometry<Real>, _: &dyn Shape, _: &dyn Shape, _: Real, _: &mut ContactManifold<ManifoldData, ContactData>) -> Result<(), Unsupported> {
+        Err(Unsupported)
+    }
+}
+
+// Route contacts and continuous collision detection through TerrainQueries while a terrain is loaded
+// Without a terrain the physics engine keeps its default queries
+pub fn use_terrain_queries(terrain: Option<Res<Terrain>>, mut context: ResMut<RapierContext>, mut installed: Local<bool>) {
+    if terrain.is_some() == *installed {
+        return;
+    }
+    *installed = terrain.is_some();
+
+    let (narrow_phase, ccd_solver) = if *installed { terrain_pipeline() } else { (NarrowPhase::new(), CCDSolver::new()) };
+    context.narrow_phase = narrow_phase;
+    context.ccd_solver = ccd_solver;
+}
+
+fn terrain_pipeline() -> (NarrowPhase, CCDSolver) {
+    (NarrowPhase::with_query_dispatcher(TerrainQueries.chain(DefaultQueryDispatcher)), CCDSolver::with_query_dispatcher(TerrainQueries.chain(DefaultQueryDispatcher)))
+}
+
+// Heights from the brightness of every pixel, 0 for black and 1 for white
+fn read_png(path: &Path) -> Result<(Vec<f32>, usize, usize), Box<dyn Error>> {
+    let image = image::open(path)?.into_luma16();
+    let (cols, rows) = image.dimensions();
+    let heights = image.pixels().map(|pixel| pixel.0[0] as f32 / u16::MAX as f32).collect();
+    Ok((heights, rows as usize, cols as usize))
+}
+
+// Heights from a csv file without a header, one row of the grid per line
+fn read_csv(path: &Path) -> Result<(Vec<f32>, usize, usize), Box<dyn Error>> {
+    let mut rdr = csv::ReaderBuilder::new().has_headers(false).flexible(true).trim(csv::Trim::All).from_path(path)?;
+    let mut heights = vec![];
+    let mut rows = 0;
+    let mut cols = 0;
+
+    for record in rdr.records() {
+        let record = record?;
+        if rows > 0 && record.len() != cols {
+            return Err(format!("line {} has {} heights, expected {}", rows + 1, record.len(), cols).into());
+        }
+        cols = record.len();
+        for value in record.iter() {
+            heights.push(value.parse::<f32>().map_err(|err| format!("line {}: {}: {}", rows + 1, value, err))?);
+        }
+        rows += 1;
+    }
+
+    Ok((heights, rows, cols))
+}
+
+// Read the terrain of the arena before a simulation starts, None when it keeps the flat floor
+pub fn load_terrain(sim_settings: &SimSettings) -> Result<Option<Terrain>, String> {
+    let settings = &sim_settings.arena.terrain;
+    if settings.path.is_empty() {
+        return Ok(None);
+    }
+    Terrain::load(settings).map(Some).map_err(|err| format!("Could not load terrain {}: {}", settings.path, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, process};
+
+    // 2 x 2 heights 2 m apart, from x = z = -1 to 1
+    fn square() -> Terrain {
+        Terrain { heights: vec![0.0, 1.0, 2.0, 4.0], rows: 2, cols: 2, cell_size: 2.0 }
+    }
+
+    fn assert_close(actual: Option<f32>, expected: f32) {
+        assert!(actual.is_some_and(|actual| (actual - expected).abs() < 1.0e-5), "expected {}, got {:?}", expected, actual);
+    }
+
+    #[test]
+    fn height_at_the_corners() {
+        let terrain = square();
+        assert_close(terrain.height_at(-1.0, -1.0), 0.0);
+        assert_close(terrain.height_at(1.0, -1.0), 1.0);
+        assert_close(terrain.height_at(-1.0, 1.0), 2.0);
+        assert_close(terrain.height_at(1.0, 1.0), 4.0);
+    }
+
+    #[test]
+    fn height_at_follows_the_diagonal_of_the_collider() {
+        let terrain = square();
+
+        // The cell is split from row 1 column 0 to row 0 column 1, not through the lowest and highest corners
+        assert_close(terrain.height_at(0.0, 0.0), 1.5);
+        assert_close(terrain.height_at(-0.5, -0.5), 0.75);
+        assert_close(terrain.height_at(0.5, 0.5), 2.75);
+
+        // Rays straight down onto the heightfield hit the same heights
+        let collider = terrain.collider();
+        for (x, z) in [(0.0, 0.0), (-0.5, -0.5), (0.5, 0.5), (0.9, -0.7), (-0.3, 0.8)] {
+            let toi = collider.cast_ray(Vec3::ZERO, Quat::IDENTITY, Vec3::new(x, 10.0, z), -Vec3::Y, 20.0, true);
+            assert_close(toi.map(|toi| 10.0 - toi), terrain.height_at(x, z).unwrap());
+        }
+    }
+
+    #[test]
+    fn height_at_beyond_the_edges() {
+        let terrain = square();
+        assert_eq!(terrain.height_at(1.1, 0.0), None);
+        assert_eq!(terrain.height_at(0.0, -1.1), None);
+        assert_close(Some(terrain.nearest_height(3.0, 3.0)), 4.0);
+    }
+
+    // Drop a steel fragment onto the terrain with the physics engine, giving where it ends up and how fast it still moves
+    fn drop_fragment(terrain: &Terrain, half_size: f32, start: Vec3, velocity: Vec3, seconds: f32) -> (Vec3, f32) {
+        use bevy_rapier3d::rapier::prelude as rapier;
+
+        let (mut narrow_phase, mut ccd_solver) = terrain_pipeline();
+        let mut bodies = rapier::RigidBodySet::new();
+        let mut colliders = rapier::ColliderSet::new();
+        colliders.insert(rapier::ColliderBuilder::new(terrain.collider().raw).friction(10.0).build());
+
+        let body = bodies.insert(rapier::RigidBodyBuilder::dynamic().translation(start.into()).linvel(velocity.into()).ccd_enabled(true).build());
+        let fragment = rapier::ColliderBuilder::cuboid(half_size, half_size, half_size).density(7850.0).friction(10.0).restitution(0.0).build();
+        colliders.insert_with_parent(fragment, body, &mut bodies);
+
+        let parameters = rapier::IntegrationParameters::default();
+        let mut pipeline = rapier::PhysicsPipeline::new();
+        let mut islands = rapier::IslandManager::new();
+        let mut broad_phase = rapier::BroadPhase::new();
+        let mut impulse_joints = rapier::ImpulseJointSet::new();
+        let mut multibody_joints = rapier::MultibodyJointSet::new();
+        for _ in 0..(seconds / parameters.dt) as usize {
+            pipeline.step(
+                &Vec3::new(0.0, -9.81, 0.0).into(),
+                &parameters,
+                &mut islands,
+                &mut broad_phase,
+                &mut narrow_phase,
+                &mut bodies,
+                &mut colliders,
+                &mut impulse_joints,
+                &mut multibody_joints,
+                &mut ccd_solver,
+                &(),
+                &(),
+            );
+        }
+
+        (Vec3::from(*bodies[body].translation()), bodies[body].linvel().norm())
+    }
+
+    #[test]
+    fn fast_fragments_rest_on_a_slope() {
+        // 40 m wide valley along z with sides rising 0.4 m per meter
+        let heights = (0..41).flat_map(|_| (0..41).map(|col: i32| 0.4 * (col - 20).abs() as f32)).collect();
+        let terrain = Terrain { heights, rows: 41, cols: 41, cell_size: 1.0 };
+
+        // A 6 mm cube moving up to 5 m per step, far more than its size
+        let half_size = 0.003;
+        for speed in [50.0, 300.0] {
+            for x in [-5.2, 2.7] {
+                let (position, speed) = drop_fragment(&terrain, half_size, Vec3::new(x, 10.0, 0.2), Vec3::new(0.0, -speed, 0.0), 5.0);
+
+                // It stays on top of the surface, not through it and not sunk into it
+                let surface = terrain.height_at(position.x, position.z).unwrap_or_else(|| panic!("the fragment left the terrain at {}", position));
+                let height = position.y - surface;
+                assert!(height > 0.0 && height < 2.0 * half_size, "the fragment is {} m above the surface at {}", height, position);
+                assert!(speed < 0.1, "the fragment still moves at {} m/s at {}", speed, position);
+            }
+        }
+    }
+
+    #[test]
+    fn csv_rows_must_have_the_same_length() {
+        let path = env::temp_dir().join(format!("frag-sim-terrain-{}.csv", process::id()));
+
+        fs::write(&path, "0, 1, 2\n3, 4, 5\n").unwrap();
+        let (heights, rows, cols) = read_csv(&path).unwrap();
+        assert_eq!((heights, rows, cols), (vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0], 2, 3));
+
+        fs::write(&path, "0, 1, 2\n3, 4\n").unwrap();
+        let err = read_csv(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(err.to_string(), "line 2 has 2 heights, expected 3");
+    }
+}
@@ -0,0 +1,163 @@
+use bevy::math::Vec3;
+use rand::Rng;
+use rand_distr::{Distribution, Normal, UnitSphere};
+use serde::Serialize;
+use std::str::FromStr;
+
+use super::sim_settings::SimSettings;
+
+// Distance from the grenade center that fragments are spawned at
+const SPAWN_RADIUS: f32 = 1.0;
+
+// How the launch direction of each fragment is chosen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DirectionModel {
+    // Each axis drawn independently from -1..1, the original behavior
+    Cube,
+    // Uniformly distributed over the unit sphere
+    Sphere,
+}
+
+impl FromStr for DirectionModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "cube" => Ok(DirectionModel::Cube),
+            "sphere" => Ok(DirectionModel::Sphere),
+            other => Err(format!("Unknown direction_model {}, expected cube or sphere.", other)),
+        }
+    }
+}
+
+// How the launch speed of each fragment is chosen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpeedModel {
+    // Every fragment flies at the explosion speed
+    Constant,
+    // Normally distributed around the explosion speed with speed_std
+    Normal,
+    // Uniformly distributed between speed_min and speed_max
+    Uniform,
+}
+
+impl FromStr for SpeedModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "constant" => Ok(SpeedModel::Constant),
+            "normal" => Ok(SpeedModel::Normal),
+            "uniform" => Ok(SpeedModel::Uniform),
+            other => Err(format!("Unknown speed_model {}, expected constant, normal or uniform.", other)),
+        }
+    }
+}
+
+// Draw the spawn offset and launch velocity of one fragment relative to the grenade
+pub fn sample_launch(sim_settings: &SimSettings, rng: &mut impl Rng) -> (Vec3, Vec3) {
+    match sim_settings.direction_model {
+        DirectionModel::Cube => {
+            // Calculate random offsets from grenade
+            let offset = Vec3::new(
+                rng.gen_range(-100..100) as f32 / 100.0,
+                rng.gen_range(-100..100) as f32 / 100.0,
+                rng.gen_range(-100..100) as f32 / 100.0,
+            );
+
+            // Calculate velocity from the offsets
+            (offset, offset * sim_settings.explosion_speed())
+        }
+        DirectionModel::Sphere => {
+            // Pick a direction on the unit sphere
+            let [x, y, z]: [f32; 3] = UnitSphere.sample(rng);
+            let direction = Vec3::new(x, y, z);
+
+            (direction * SPAWN_RADIUS, direction * sample_speed(sim_settings, rng))
+        }
+    }
+}
+
+// Draw a launch speed from the configured speed model
+fn sample_speed(sim_settings: &SimSettings, rng: &mut impl Rng) -> f32 {
+    let explosion_speed = sim_settings.explosion_speed();
+    let speed = match sim_settings.speed_model {
+        SpeedModel::Constant => explosion_speed,
+        SpeedModel::Normal => match Normal::new(explosion_speed, sim_settings.speed_std.abs()) {
+            Ok(normal) => normal.sample(rng),
+            Err(_) => explosion_speed,
+        },
+        SpeedModel::Uniform => {
+            let (min, max) = (sim_settings.speed_min, sim_settings.speed_max);
+            if min < max {
+                rng.gen_range(min..max)
+            } else {
+                min
+            }
+        }
+    };
+
+    // Fragments can not fly backwards through the casing
+    speed.max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn settings(direction_model: DirectionModel, speed_model: SpeedModel) -> SimSettings {
+        SimSettings { direction_model, speed_model, explosion_vel: Some(400.0), ..SimSettings::default() }
+    }
+
+    // Spawn offset and launch velocity of 2000 fragments
+    fn launches(sim_settings: &SimSettings, seed: u64) -> Vec<(Vec3, Vec3)> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..2000).map(|_| sample_launch(sim_settings, &mut rng)).collect()
+    }
+
+    fn speeds(sim_settings: &SimSettings) -> Vec<f32> {
+        launches(sim_settings, 7).iter().map(|(_, velocity)| velocity.length()).collect()
+    }
+
+    #[test]
+    fn sphere_directions_are_unit_vectors_spread_evenly() {
+        let launches = launches(&settings(DirectionModel::Sphere, SpeedModel::Constant), 7);
+        assert!(launches.iter().all(|(offset, _)| (offset.length() - 1.0).abs() < 1.0e-5));
+
+        // Directions over the whole sphere cancel out
+        let mean = launches.iter().fold(Vec3::ZERO, |sum, (offset, _)| sum + *offset) / launches.len() as f32;
+        assert!(mean.length() < 0.05, "mean direction is {}", mean);
+    }
+
+    #[test]
+    fn constant_speed_is_the_explosion_speed() {
+        assert!(speeds(&settings(DirectionModel::Sphere, SpeedModel::Constant)).iter().all(|speed| (speed - 400.0).abs() < 1.0e-2));
+    }
+
+    #[test]
+    fn uniform_speeds_stay_in_their_range() {
+        let sim_settings = SimSettings { speed_min: 200.0, speed_max: 300.0, ..settings(DirectionModel::Sphere, SpeedModel::Uniform) };
+        let speeds = speeds(&sim_settings);
+        assert!(speeds.iter().all(|speed| (200.0 - 1.0e-2..=300.0 + 1.0e-2).contains(speed)), "a speed is outside 200..300");
+        assert!(speeds.iter().any(|speed| *speed < 210.0) && speeds.iter().any(|speed| *speed > 290.0));
+    }
+
+    #[test]
+    fn normal_speeds_are_never_negative() {
+        // A spread wider than the mean would draw many negative speeds
+        let sim_settings = SimSettings { speed_std: 800.0, ..settings(DirectionModel::Sphere, SpeedModel::Normal) };
+        let speeds = speeds(&sim_settings);
+        assert!(speeds.iter().all(|speed| *speed >= 0.0));
+        assert!(speeds.contains(&0.0) && speeds.iter().any(|speed| *speed > 400.0));
+    }
+
+    #[test]
+    fn launches_repeat_with_a_seed() {
+        let sim_settings = SimSettings { speed_std: 50.0, ..settings(DirectionModel::Sphere, SpeedModel::Normal) };
+        assert_eq!(launches(&sim_settings, 42), launches(&sim_settings, 42));
+        assert_ne!(launches(&sim_settings, 42), launches(&sim_settings, 43));
+    }
+}
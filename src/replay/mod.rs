@@ -0,0 +1,246 @@
+use bevy::{prelude::*, app::AppExit};
+use bevy_flycam::NoCameraPlayerPlugin;
+use std::{collections::BTreeMap, error::Error, path::Path};
+
+use crate::{output::{OutputFormat, metadata::metadata_path}, physics::{lock_cursor, sim_settings::SimSettings, sim_setup::{self, Ground}}};
+
+// Create a plugin that plays back a data file instead of simulating
+pub struct ReplayPlugin {
+    // Simulated seconds shown per real second
+    pub speed: f32,
+}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            // Start playing from the explosion
+            .insert_resource(ReplayClock { elapsed: 0.0, speed: self.speed, paused: false })
+
+            // Spawn the room, light and moveable camera the simulation uses
+            .add_startup_system(sim_setup::spawn_solid_surfaces)
+            .add_startup_system(sim_setup::spawn_light)
+            .add_startup_system(sim_setup::spawn_camera)
+
+            // Move the open field ground under the detonation once it is spawned, as the simulation does
+            .add_startup_system_to_stage(StartupStage::PostStartup, center_ground)
+
+            // Spawn a cube for every recorded fragment
+            .add_startup_system(spawn_fragments)
+
+            // Move the fragments along their recorded paths
+            .add_system(advance_replay_clock.before(move_fragments))
+            .add_system(move_fragments)
+
+            // Handle pausing, restarting and quitting
+            .add_system(replay_controls)
+
+            // Handle cursor locking and camera movement
+            .add_system(lock_cursor)
+            .add_plugin(NoCameraPlayerPlugin);
+    }
+}
+
+// Recorded path of every fragment
+pub struct Replay {
+    tracks: Vec<Track>,
+    duration: f32,
+    // Where the grenade exploded
+    detonation: Vec3,
+}
+
+// Recorded positions of one fragment, in time order
+struct Track {
+    size: f32,
+    times: Vec<f32>,
+    positions: Vec<Vec3>,
+    // When the fragment left the tracking radius, it is hidden after that
+    escaped_at: Option<f32>,
+}
+
+impl Track {
+    // Position at a point in simulated time, between the two closest records
+    fn position_at(&self, time: f32) -> Vec3 {
+        let next = self.times.partition_point(|sample_time| *sample_time <= time);
+        if next == 0 {
+            return self.positions[0];
+        }
+        if next == self.times.len() {
+            return self.positions[next - 1];
+        }
+
+        let (start, end) = (self.times[next - 1], self.times[next]);
+        let fraction = if end > start { (time - start) / (end - start) } else { 0.0 };
+        self.positions[next - 1].lerp(self.positions[next], fraction)
+    }
+}
+
+// Simulated time being shown
+struct ReplayClock {
+    elapsed: f32,
+    speed: f32,
+    paused: bool,
+}
+
+#[derive(Component)]
+struct ReplayFragment(usize);
+
+// Read the metadata file of a data file, the first run of a file that runs were appended to
+fn read_metadata(data_file: &Path) -> Result<serde_json::Value, String> {
+    let text = std::fs::read_to_string(metadata_path(data_file)).map_err(|err| err.to_string())?;
+    let metadata = serde_json::from_str::<serde_json::Value>(&text).map_err(|err| err.to_string())?;
+    Ok(if metadata.is_array() { metadata[0].clone() } else { metadata })
+}
+
+// Read the settings a data file was written with from its metadata file, so the arena matches the run
+pub fn load_settings(data_file: &Path) -> SimSettings {
+    let settings = read_metadata(data_file).and_then(|metadata| serde_json::from_value(metadata["settings"].clone()).map_err(|err| err.to_string()));
+
+    settings.unwrap_or_else(|err| {
+        println!("Could not read the settings from {}: {}. Using the default arena.", metadata_path(data_file).display(), err);
+        SimSettings::default()
+    })
+}
+
+// Read the fragment paths from a csv file in the long layout
+// Only the first run is read from a file that runs were appended to
+pub fn load_replay(path: &Path) -> Result<Replay, Box<dyn Error>> {
+    if OutputFormat::from_path(&path.to_string_lossy()) != OutputFormat::Csv {
+        return Err("only csv files in the long layout can be replayed".into());
+    }
+
+    let mut rdr = csv::Reader::from_path(path)?;
+
+    // Find the columns by name
+    let headers = rdr.headers()?.clone();
+    let column = |name: &str| {
+        headers.iter().position(|header| header == name).ok_or_else(|| format!("missing column {}, the file has to use the long layout", name))
+    };
+    let (time_col, id_col, size_col) = (column("sim_time")?, column("fragment_id")?, column("size")?);
+    let (x_col, y_col, z_col) = (column("x")?, column("y")?, column("z")?);
+    let event_col = column("event")?;
+
+    let mut tracks: BTreeMap<u32, Track> = BTreeMap::new();
+    let mut last_time = 0.0;
+
+    for record in rdr.records() {
+        let record = record?;
+        let field = |col: usize| record.get(col).unwrap_or_default();
+        let time: f32 = field(time_col).parse()?;
+
+        // Time going backwards means the next run starts
+        if time < last_time {
+            break;
+        }
+        last_time = time;
+
+        let position = Vec3::new(field(x_col).parse()?, field(y_col).parse()?, field(z_col).parse()?);
+        let track = tracks.entry(field(id_col).parse()?).or_insert(Track {
+            size: field(size_col).parse()?,
+            times: vec![],
+            positions: vec![],
+            escaped_at: None,
+        });
+        track.times.push(time);
+        track.positions.push(position);
+        if field(event_col) == "escaped" {
+            track.escaped_at = Some(time);
+        }
+    }
+
+    if tracks.is_empty() {
+        return Err("the file has no fragment rows".into());
+    }
+
+    // The detonation is in the metadata, else it is in the middle of where the fragments were launched
+    let detonation = read_metadata(path)
+        .ok()
+        .and_then(|metadata| serde_json::from_value(metadata["detonation"]["position"].clone()).ok())
+        .unwrap_or_else(|| tracks.values().map(|track| track.positions[0]).fold(Vec3::ZERO, |sum, position| sum + position) / tracks.len() as f32);
+
+    Ok(Replay { tracks: tracks.into_values().collect(), duration: last_time, detonation })
+}
+
+// Center the open field ground on the detonation, as the simulation does before the fragments fly
+fn center_ground(replay: Res<Replay>, ground: Query<&mut Transform, With<Ground>>) {
+    sim_setup::move_ground(replay.detonation, ground);
+}
+
+// Spawn a cube for every recorded fragment
+fn spawn_fragments(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    replay: Res<Replay>,
+) {
+    for (index, track) in replay.tracks.iter().enumerate() {
+        commands.spawn_bundle(PbrBundle {
+            mesh: meshes.add(Mesh::from(shape::Cube { size: track.size })),
+
+            // Set the color to red
+            material: materials.add(Color::rgb(1.0, 0.0, 0.0).into()),
+
+            // Start at the first recorded position
+            transform: Transform::from_translation(track.positions[0]),
+            ..default()
+        })
+        .insert(ReplayFragment(index));
+    }
+
+    println!("Replaying {} fragments over {:.3}s. Space pauses, R restarts and Q quits.", replay.tracks.len(), replay.duration);
+}
+
+// Advance the shown time by the frame time, stopping at the end of the recording
+fn advance_replay_clock(mut clock: ResMut<ReplayClock>, replay: Res<Replay>, time: Res<Time>) {
+    if !clock.paused {
+        clock.elapsed = (clock.elapsed + time.delta_seconds() * clock.speed).min(replay.duration);
+    }
+}
+
+// Put every fragment where it was at the shown time, hiding the ones that have escaped
+fn move_fragments(clock: Res<ReplayClock>, replay: Res<Replay>, mut fragments: Query<(&ReplayFragment, &mut Transform, &mut Visibility)>) {
+    for (fragment, mut transform, mut visibility) in fragments.iter_mut() {
+        let track = &replay.tracks[fragment.0];
+        transform.translation = track.position_at(clock.elapsed);
+        visibility.is_visible = !track.escaped_at.is_some_and(|escaped_at| clock.elapsed > escaped_at);
+    }
+}
+
+// Space pauses, R restarts and Q quits
+fn replay_controls(keys: Res<Input<KeyCode>>, mut clock: ResMut<ReplayClock>, mut exit: EventWriter<AppExit>) {
+    if keys.just_pressed(KeyCode::Space) {
+        clock.paused = !clock.paused;
+    }
+    if keys.just_pressed(KeyCode::R) {
+        clock.elapsed = 0.0;
+    }
+    if keys.just_pressed(KeyCode::Q) {
+        exit.send(AppExit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, process};
+
+    #[test]
+    fn detonation_comes_from_the_metadata() {
+        let path = env::temp_dir().join(format!("frag-sim-replay-{}.csv", process::id()));
+        fs::write(&path, "\
+sim_time,fragment_id,event,x,y,z,vx,vy,vz,speed,mass,size,seed,hit
+0,0,launch,11,2,-3,0,0,0,0,0.001,0.01,1,
+0,1,launch,9,2,-3,0,0,0,0,0.001,0.01,1,
+0.5,0,sample,20,1,-3,0,0,0,0,0.001,0.01,1,
+").unwrap();
+
+        // Without metadata the grenade was in the middle of the launches
+        assert_eq!(load_replay(&path).unwrap().detonation, Vec3::new(10.0, 2.0, -3.0));
+
+        // The metadata of appended runs is a list, the first run is replayed
+        fs::write(metadata_path(&path), r#"[{"detonation": {"position": [10.5, 2.5, -3.5]}}, {"detonation": {"position": [0, 0, 0]}}]"#).unwrap();
+        assert_eq!(load_replay(&path).unwrap().detonation, Vec3::new(10.5, 2.5, -3.5));
+
+        fs::remove_file(metadata_path(&path)).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+}